@@ -0,0 +1,243 @@
+//!
+//! Resource backends responsible for loading and updating resources
+//! of a specific resource file type.
+//!
+
+use crate::coff::{self, CoffBackend};
+use crate::data::Mapping;
+use crate::id::Id;
+use crate::ne::{self, NeBackend};
+use crate::pe::{PeBackend, SignaturePolicy};
+use crate::res::{self, ResBackend};
+use crate::resources::{ResourceEntry, ResourceMetadata};
+use crate::result::Result;
+use crate::rsrc::DirectoryMetadata;
+use crate::utils::write_file_atomic;
+#[cfg(all(windows, feature = "win32"))]
+use crate::win32::Win32Backend;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Number of leading bytes used to detect the resource file type
+const DETECT_HEADER_SIZE: usize = 0x40;
+
+/// Storage holding the contents of a resource file: a file on disk or an
+/// in-memory buffer shared between the resource manager and its backend.
+#[derive(Debug, Clone)]
+pub enum Storage {
+    File(PathBuf),
+    Memory(Arc<Mutex<Vec<u8>>>),
+}
+
+impl Storage {
+    /// Create an in-memory storage holding a copy of the supplied data.
+    pub fn from_bytes(data: &[u8]) -> Storage {
+        Storage::Memory(Arc::new(Mutex::new(data.to_vec())))
+    }
+
+    /// Read the contents of the storage.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        match self {
+            Storage::File(file) => Ok(std::fs::read(file)?),
+            Storage::Memory(data) => Ok(data.lock().unwrap().clone()),
+        }
+    }
+
+    /// Map the contents of the storage. Files are memory-mapped, the contents
+    /// of in-memory storage are copied once and shared by the resources loaded from it.
+    pub fn map(&self) -> Result<Mapping> {
+        match self {
+            Storage::File(file) => Mapping::map(file),
+            Storage::Memory(data) => Ok(Mapping::from_vec(data.lock().unwrap().clone())),
+        }
+    }
+
+    /// Replace the contents of the storage. Files are replaced atomically by
+    /// renaming a temporary file written next to them.
+    pub fn write(&self, data: Vec<u8>) -> Result<()> {
+        match self {
+            Storage::File(file) => write_file_atomic(file, &data)?,
+            Storage::Memory(buffer) => *buffer.lock().unwrap() = data,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Storage::File(file) => write!(f, "{}", file.display()),
+            Storage::Memory(_) => write!(f, "<memory>"),
+        }
+    }
+}
+
+/// Interface implemented by resource backends. A backend is bound to a
+/// single resource file; updates are staged between [`ResourceBackend::begin`]
+/// and [`ResourceBackend::commit`] (or [`ResourceBackend::discard`]).
+pub trait ResourceBackend: fmt::Debug + Send {
+    /// Load all resources contained in the resource file.
+    fn load(&self) -> Result<Vec<ResourceEntry>>;
+
+    /// Start an update session. If `delete_existing_resources` is `true`, the
+    /// resulting file will only contain resources staged during this session.
+    fn begin(&mut self, delete_existing_resources: bool) -> Result<()>;
+
+    /// Stage creation or replacement of the resource identified by `kind`, `name` and `lang`.
+    fn update(&mut self, kind: &Id, name: &Id, lang: u16, data: &[u8]) -> Result<()>;
+
+    /// Stage the attributes of the resource identified by `kind`, `name` and `lang`,
+    /// which must have been created or replaced during this session. Ignored by
+    /// backends of file formats that do not store resource attributes.
+    fn set_metadata(
+        &mut self,
+        _kind: &Id,
+        _name: &Id,
+        _lang: u16,
+        _metadata: &ResourceMetadata,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Stage removal of the resource identified by `kind`, `name` and `lang`.
+    fn delete(&mut self, kind: &Id, name: &Id, lang: u16) -> Result<()>;
+
+    /// Apply staged changes to the resource file and end the update session.
+    fn commit(&mut self) -> Result<()>;
+
+    /// Apply staged changes writing the result to the supplied storage instead
+    /// of the resource file (which is left unchanged) and end the update session.
+    fn commit_to(&mut self, storage: &Storage) -> Result<()>;
+
+    /// End the update session discarding all staged changes.
+    fn discard(&mut self) -> Result<()>;
+
+    /// Stage replacement of the overlay (data appended past the last section of
+    /// an executable). The existing overlay is retained unless replaced.
+    fn set_overlay(&mut self, _overlay: &[u8]) -> Result<()> {
+        Err("overlay data is not supported by this resource file type".into())
+    }
+
+    /// Load the attributes of the resource directory. Returns `None` for file
+    /// formats without a resource directory or files without resources.
+    fn load_directory(&self) -> Result<Option<DirectoryMetadata>> {
+        Ok(None)
+    }
+
+    /// Stage replacement of the resource directory attributes. The existing
+    /// attributes are retained unless replaced.
+    fn set_directory(&mut self, _directory: &DirectoryMetadata) -> Result<()> {
+        Err("resource directory attributes are not supported by this resource file type".into())
+    }
+
+    /// Select whether the time stamp of the resource directory is zeroed on
+    /// commit, producing reproducible output. Ignored by backends of file
+    /// formats without time stamps.
+    fn set_deterministic(&mut self, _deterministic: bool) {}
+
+    /// Select how the certificate table of signed images is handled; must be
+    /// called before [`ResourceBackend::begin`]. Ignored by backends of file
+    /// formats that can not be signed.
+    fn set_signature_policy(&mut self, _policy: SignaturePolicy) {}
+
+    /// Select whether the image `CheckSum` is recomputed on commit. Ignored by
+    /// backends of file formats without a checksum.
+    fn set_update_checksum(&mut self, _update_checksum: bool) {}
+}
+
+/// Resource backend implementations available in this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Win32 resource API (`LoadLibraryExA`, `BeginUpdateResourceA` etc.).
+    /// Available on Windows with the `win32` feature enabled.
+    #[cfg(all(windows, feature = "win32"))]
+    Win32,
+    /// Pure-Rust PE image (`.exe`, `.dll`) resource section reader and writer
+    Pe,
+    /// Pure-Rust 32-bit compiled resource (`.res`) file reader and writer
+    Res,
+    /// Pure-Rust COFF object file (`.obj`) and archive (`.lib`) reader (read-only)
+    Coff,
+    /// Pure-Rust 16-bit NE image (Win16 executables, `.icl` icon libraries) reader (read-only)
+    Ne,
+}
+
+impl BackendKind {
+    /// Select the backend suitable for the supplied file. Compiled resource files
+    /// use [`BackendKind::Res`], object files and archives use [`BackendKind::Coff`],
+    /// NE images use [`BackendKind::Ne`]; executables use [`BackendKind::Win32`]
+    /// if available and [`BackendKind::Pe`] otherwise.
+    pub fn detect(file: &Path) -> Result<BackendKind> {
+        let mut file = std::fs::File::open(file)?;
+        let mut header = Vec::with_capacity(DETECT_HEADER_SIZE);
+        (&mut file)
+            .take(DETECT_HEADER_SIZE as u64)
+            .read_to_end(&mut header)?;
+
+        if let Some(offset) = ne::new_header_offset(&header) {
+            let mut signature = Vec::with_capacity(2);
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.take(2).read_to_end(&mut signature)?;
+            if signature == ne::IMAGE_OS2_SIGNATURE.to_le_bytes() {
+                return Ok(BackendKind::Ne);
+            }
+        }
+
+        match BackendKind::detect_bytes(&header) {
+            #[cfg(all(windows, feature = "win32"))]
+            BackendKind::Pe => Ok(BackendKind::Win32),
+            backend_kind => Ok(backend_kind),
+        }
+    }
+
+    /// Select the pure-Rust backend suitable for the supplied file contents.
+    pub fn detect_bytes(data: &[u8]) -> BackendKind {
+        if res::is_res_file(data) {
+            BackendKind::Res
+        } else if coff::is_object_file(data) || coff::is_archive_file(data) {
+            BackendKind::Coff
+        } else if ne::is_ne_image(data) {
+            BackendKind::Ne
+        } else {
+            BackendKind::Pe
+        }
+    }
+
+    /// Select the backend suitable for the contents of the supplied storage.
+    pub fn detect_storage(storage: &Storage) -> Result<BackendKind> {
+        match storage {
+            Storage::File(file) => BackendKind::detect(file),
+            Storage::Memory(data) => Ok(BackendKind::detect_bytes(&data.lock().unwrap())),
+        }
+    }
+
+    /// Create a backend of this kind bound to the supplied file.
+    pub fn create(&self, file: &Path) -> Box<dyn ResourceBackend> {
+        match self {
+            #[cfg(all(windows, feature = "win32"))]
+            BackendKind::Win32 => Box::new(Win32Backend::new(file)),
+            BackendKind::Pe => Box::new(PeBackend::new(file)),
+            BackendKind::Res => Box::new(ResBackend::new(file)),
+            BackendKind::Coff => Box::new(CoffBackend::new(file)),
+            BackendKind::Ne => Box::new(NeBackend::new(file)),
+        }
+    }
+
+    /// Create a backend of this kind bound to the supplied storage. The Win32
+    /// backend can only be bound to a file.
+    pub fn create_with_storage(&self, storage: Storage) -> Result<Box<dyn ResourceBackend>> {
+        match (self, storage) {
+            (_, Storage::File(file)) => Ok(self.create(&file)),
+            #[cfg(all(windows, feature = "win32"))]
+            (BackendKind::Win32, Storage::Memory(_)) => {
+                Err("the Win32 backend can not access in-memory resource files".into())
+            }
+            (BackendKind::Pe, storage) => Ok(Box::new(PeBackend::with_storage(storage))),
+            (BackendKind::Res, storage) => Ok(Box::new(ResBackend::with_storage(storage))),
+            (BackendKind::Coff, storage) => Ok(Box::new(CoffBackend::with_storage(storage))),
+            (BackendKind::Ne, storage) => Ok(Box::new(NeBackend::with_storage(storage))),
+        }
+    }
+}
@@ -0,0 +1,515 @@
+//!
+//! COFF object file (`.obj`) support for resources stored in the
+//! `.rsrc$01` (directory tree) and `.rsrc$02` (resource data) sections,
+//! equivalent to the output of `cvtres.exe`, as well as static libraries
+//! (`.lib` archives) containing such objects.
+//!
+
+use crate::backend::{ResourceBackend, Storage};
+use crate::data::{self, Mapping};
+use crate::error::Error;
+use crate::id::Id;
+use crate::pe::{Section, IMAGE_FILE_HEADER_SIZE, IMAGE_SECTION_HEADER_SIZE};
+use crate::resources::ResourceEntry;
+use crate::result::Result;
+use crate::rsrc::{self, DirectoryMetadata};
+use crate::utils::*;
+use manual_serializer::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Signature of archive (`.lib`) files
+pub const IMAGE_ARCHIVE_START: &[u8] = b"!<arch>\n";
+/// Byte size of the `IMAGE_ARCHIVE_MEMBER_HEADER` structure
+pub const IMAGE_ARCHIVE_MEMBER_HEADER_SIZE: usize = 60;
+
+/// Byte size of the `IMAGE_RELOCATION` record
+pub const IMAGE_RELOCATION_SIZE: usize = 10;
+/// Byte size of the `IMAGE_SYMBOL` record
+pub const IMAGE_SYMBOL_SIZE: usize = 18;
+
+/// Absolute symbol section number
+const IMAGE_SYM_ABSOLUTE: i16 = -1;
+/// Static symbol storage class
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+/// Image file header flag marking 32-bit machines
+const IMAGE_FILE_32BIT_MACHINE: u16 = 0x0100;
+/// Section characteristics of the resource sections
+const RSRC_SECTION_CHARACTERISTICS: u32 = 0xc000_0040;
+
+/// Machine types supported for resource object files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    /// Intel 386 (`IMAGE_FILE_MACHINE_I386`)
+    X86,
+    /// x64 (`IMAGE_FILE_MACHINE_AMD64`)
+    X64,
+    /// ARM64 little endian (`IMAGE_FILE_MACHINE_ARM64`)
+    Arm64,
+    /// ARM64 emulation compatible (`IMAGE_FILE_MACHINE_ARM64EC`)
+    Arm64EC,
+}
+
+impl Machine {
+    /// `IMAGE_FILE_HEADER::Machine` value
+    pub fn value(&self) -> u16 {
+        match self {
+            Machine::X86 => 0x014c,
+            Machine::X64 => 0x8664,
+            Machine::Arm64 => 0xaa64,
+            Machine::Arm64EC => 0xa641,
+        }
+    }
+
+    /// Relocation type producing a 32-bit image relative address (`ADDR32NB`)
+    pub fn addr32nb_relocation(&self) -> u16 {
+        match self {
+            // IMAGE_REL_I386_DIR32NB
+            Machine::X86 => 0x0007,
+            // IMAGE_REL_AMD64_ADDR32NB
+            Machine::X64 => 0x0003,
+            // IMAGE_REL_ARM64_ADDR32NB
+            Machine::Arm64 | Machine::Arm64EC => 0x0002,
+        }
+    }
+
+    /// Returns `true` for 32-bit machines
+    pub fn is_32bit(&self) -> bool {
+        matches!(self, Machine::X86)
+    }
+}
+
+impl TryFrom<u16> for Machine {
+    type Error = crate::error::Error;
+    fn try_from(value: u16) -> Result<Machine> {
+        match value {
+            0x014c => Ok(Machine::X86),
+            0x8664 => Ok(Machine::X64),
+            0xaa64 => Ok(Machine::Arm64),
+            0xa641 => Ok(Machine::Arm64EC),
+            _ => Err(format!("unsupported machine type 0x{value:04x}").into()),
+        }
+    }
+}
+
+/// Append an `IMAGE_SYMBOL` record to `dest`.
+fn store_symbol(
+    dest: &mut Vec<u8>,
+    name: &[u8],
+    value: u32,
+    section_number: i16,
+    number_of_aux_symbols: u8,
+) {
+    let mut short_name = [0u8; 8];
+    short_name[..name.len()].copy_from_slice(name);
+    dest.extend_from_slice(&short_name);
+    dest.extend_from_slice(&value.to_le_bytes());
+    dest.extend_from_slice(&section_number.to_le_bytes());
+    dest.extend_from_slice(&0u16.to_le_bytes());
+    dest.push(IMAGE_SYM_CLASS_STATIC);
+    dest.push(number_of_aux_symbols);
+}
+
+/// Append a section definition auxiliary symbol record to `dest`.
+fn store_section_aux_symbol(dest: &mut Vec<u8>, length: usize, number_of_relocations: usize) {
+    let mut aux = [0u8; IMAGE_SYMBOL_SIZE];
+    store_u32le(&mut aux[0..], length as u32);
+    store_u16le(&mut aux[4..], number_of_relocations as u16);
+    dest.extend_from_slice(&aux);
+}
+
+/// Append an `IMAGE_SECTION_HEADER` for an object file section to `dest`.
+fn store_section_header(
+    dest: &mut Vec<u8>,
+    name: &[u8; 8],
+    size_of_raw_data: usize,
+    pointer_to_raw_data: usize,
+    pointer_to_relocations: usize,
+    number_of_relocations: usize,
+) {
+    let mut header = [0u8; IMAGE_SECTION_HEADER_SIZE];
+    header[0..8].copy_from_slice(name);
+    store_u32le(&mut header[16..], size_of_raw_data as u32);
+    store_u32le(&mut header[20..], pointer_to_raw_data as u32);
+    store_u32le(&mut header[24..], pointer_to_relocations as u32);
+    store_u16le(&mut header[32..], number_of_relocations as u16);
+    store_u32le(&mut header[36..], RSRC_SECTION_CHARACTERISTICS);
+    dest.extend_from_slice(&header);
+}
+
+/// Serialize the supplied resources into a COFF object file for the given machine.
+/// The `.rsrc$01` section holds the directory tree whose data entries are
+/// relocated (`ADDR32NB`) against the `.rsrc$02` section holding the data.
+pub fn write_object(entries: &[ResourceEntry], machine: Machine) -> Result<Vec<u8>> {
+    let layout = rsrc::build_directory_tree(entries, &DirectoryMetadata::default())?;
+    if layout.data_entries.len() > u16::MAX as usize {
+        return Err("COFF object: too many resources to relocate".into());
+    }
+
+    let sections_offset = IMAGE_FILE_HEADER_SIZE + 2 * IMAGE_SECTION_HEADER_SIZE;
+    let relocations_offset = sections_offset + layout.tree.len();
+    let data_offset = relocations_offset + layout.data_entries.len() * IMAGE_RELOCATION_SIZE;
+    let symbols_offset = data_offset + layout.data.len();
+    // @feat.00, .rsrc$01 + aux, .rsrc$02 + aux
+    let number_of_symbols = 5u32;
+    let rsrc02_symbol = 3u32;
+
+    let mut object = Vec::with_capacity(symbols_offset + 5 * IMAGE_SYMBOL_SIZE + 4);
+    object.extend_from_slice(&machine.value().to_le_bytes());
+    object.extend_from_slice(&2u16.to_le_bytes());
+    object.extend_from_slice(&0u32.to_le_bytes());
+    object.extend_from_slice(&(symbols_offset as u32).to_le_bytes());
+    object.extend_from_slice(&number_of_symbols.to_le_bytes());
+    object.extend_from_slice(&0u16.to_le_bytes());
+    let characteristics = if machine.is_32bit() {
+        IMAGE_FILE_32BIT_MACHINE
+    } else {
+        0
+    };
+    object.extend_from_slice(&characteristics.to_le_bytes());
+
+    store_section_header(
+        &mut object,
+        b".rsrc$01",
+        layout.tree.len(),
+        sections_offset,
+        relocations_offset,
+        layout.data_entries.len(),
+    );
+    store_section_header(
+        &mut object,
+        b".rsrc$02",
+        layout.data.len(),
+        data_offset,
+        0,
+        0,
+    );
+
+    object.extend_from_slice(&layout.tree);
+    for offset in &layout.data_entries {
+        object.extend_from_slice(&(*offset as u32).to_le_bytes());
+        object.extend_from_slice(&rsrc02_symbol.to_le_bytes());
+        object.extend_from_slice(&machine.addr32nb_relocation().to_le_bytes());
+    }
+    object.extend_from_slice(&layout.data);
+
+    store_symbol(&mut object, b"@feat.00", 0x11, IMAGE_SYM_ABSOLUTE, 0);
+    store_symbol(&mut object, b".rsrc$01", 0, 1, 1);
+    store_section_aux_symbol(&mut object, layout.tree.len(), layout.data_entries.len());
+    store_symbol(&mut object, b".rsrc$02", 0, 2, 1);
+    store_section_aux_symbol(&mut object, layout.data.len(), 0);
+    // empty string table
+    object.extend_from_slice(&4u32.to_le_bytes());
+
+    Ok(object)
+}
+
+/// Returns `true` if the supplied data starts with a COFF object file header
+/// of a supported machine type.
+pub fn is_object_file(data: &[u8]) -> bool {
+    if data.len() < IMAGE_FILE_HEADER_SIZE {
+        return false;
+    }
+    let machine = load_u16le(&data[0..2]);
+    let size_of_optional_header = load_u16le(&data[16..18]);
+    Machine::try_from(machine).is_ok() && size_of_optional_header == 0
+}
+
+/// Returns `true` if the supplied data starts with the archive (`.lib`) signature.
+pub fn is_archive_file(data: &[u8]) -> bool {
+    data.starts_with(IMAGE_ARCHIVE_START)
+}
+
+/// COFF relocation record (`IMAGE_RELOCATION`).
+#[derive(Debug, Clone, Copy)]
+pub struct Relocation {
+    pub virtual_address: u32,
+    pub symbol_table_index: u32,
+    pub kind: u16,
+}
+
+impl TryDeserialize for Relocation {
+    type Error = Error;
+    fn try_deserialize(src: &mut Deserializer) -> Result<Relocation> {
+        Ok(Relocation {
+            virtual_address: src.try_load_u32le()?,
+            symbol_table_index: src.try_load_u32le()?,
+            kind: src.try_load_u16le()?,
+        })
+    }
+}
+
+/// Parsed view of a COFF object file referring to the underlying file data.
+#[derive(Debug, Clone)]
+pub struct ObjectFile<'data> {
+    data: &'data [u8],
+    pub machine: Machine,
+    pub sections: Vec<Section>,
+    /// file offset of the symbol table
+    symbol_table_offset: usize,
+    number_of_symbols: usize,
+}
+
+impl<'data> ObjectFile<'data> {
+    /// Parse the file header and the section table of the supplied object file data.
+    pub fn parse(data: &'data [u8]) -> Result<ObjectFile<'data>> {
+        let mut src = Deserializer::new(try_slice(data, 0, IMAGE_FILE_HEADER_SIZE)?);
+        let machine = Machine::try_from(src.try_load_u16le()?)?;
+        let number_of_sections = src.try_load_u16le()? as usize;
+        src.try_offset(4)?;
+        let symbol_table_offset = src.try_load_u32le()? as usize;
+        let number_of_symbols = src.try_load_u32le()? as usize;
+        if src.try_load_u16le()? != 0 {
+            return Err("ObjectFile::parse(): unexpected optional header".into());
+        }
+
+        let mut src = Deserializer::new(try_slice(
+            data,
+            IMAGE_FILE_HEADER_SIZE,
+            number_of_sections * IMAGE_SECTION_HEADER_SIZE,
+        )?);
+        let mut sections = Vec::with_capacity(number_of_sections);
+        for _ in 0..number_of_sections {
+            sections.push(src.try_load()?);
+        }
+
+        Ok(ObjectFile {
+            data,
+            machine,
+            sections,
+            symbol_table_offset,
+            number_of_symbols,
+        })
+    }
+
+    fn section(&self, index: usize) -> Result<&Section> {
+        self.sections
+            .get(index)
+            .ok_or_else(|| format!("ObjectFile: invalid section index {index}").into())
+    }
+
+    /// Raw data of the section at `index`
+    pub fn section_data(&self, index: usize) -> Result<&'data [u8]> {
+        let section = self.section(index)?;
+        try_slice(
+            self.data,
+            section.pointer_to_raw_data as usize,
+            section.size_of_raw_data as usize,
+        )
+    }
+
+    /// Relocations applied to the section at `index`
+    pub fn relocations(&self, index: usize) -> Result<Vec<Relocation>> {
+        let section = self.section(index)?;
+        let count = section.number_of_relocations as usize;
+        let mut src = Deserializer::new(try_slice(
+            self.data,
+            section.pointer_to_relocations as usize,
+            count * IMAGE_RELOCATION_SIZE,
+        )?);
+        let mut relocations = Vec::with_capacity(count);
+        for _ in 0..count {
+            relocations.push(src.try_load()?);
+        }
+        Ok(relocations)
+    }
+
+    /// Resolve the symbol at `index` to the index of the defining section
+    /// and the symbol value (offset within that section).
+    fn symbol(&self, index: usize) -> Result<(usize, u32)> {
+        if index >= self.number_of_symbols {
+            return Err(format!("ObjectFile: invalid symbol index {index}").into());
+        }
+        let symbol = try_slice(
+            self.data,
+            self.symbol_table_offset + index * IMAGE_SYMBOL_SIZE,
+            IMAGE_SYMBOL_SIZE,
+        )?;
+        let value = load_u32le(&symbol[8..12]);
+        let section_number = load_u16le(&symbol[12..14]) as i16;
+        if section_number <= 0 || section_number as usize > self.sections.len() {
+            return Err(format!(
+                "ObjectFile: symbol {index} is not defined in a section of this object"
+            )
+            .into());
+        }
+        Ok((section_number as usize - 1, value))
+    }
+
+    /// Read all resources contained in the resource sections of this object.
+    /// The directory tree is located in `.rsrc$01` (or `.rsrc` for objects
+    /// produced by GNU `windres`) and data entries are resolved using the
+    /// relocations applied to the tree.
+    pub fn resources(&self) -> Result<Vec<ResourceEntry>> {
+        self.resources_with(None)
+    }
+
+    /// Read all resources contained in the resource sections of this object,
+    /// borrowing their data from `mapping` if the object is parsed from its contents.
+    pub(crate) fn resources_with(&self, mapping: Option<&Mapping>) -> Result<Vec<ResourceEntry>> {
+        let index = match self.resource_section() {
+            Some(index) => index,
+            None => return Ok(Vec::new()),
+        };
+
+        let tree = self.section_data(index)?;
+        let base = self.sections[index].virtual_address;
+        let mut relocations = HashMap::new();
+        for relocation in self.relocations(index)? {
+            if relocation.kind != self.machine.addr32nb_relocation() {
+                return Err(format!(
+                    "ObjectFile: unsupported relocation type 0x{:x} in resource section",
+                    relocation.kind
+                )
+                .into());
+            }
+            relocations.insert(
+                relocation.virtual_address.wrapping_sub(base) as usize,
+                relocation.symbol_table_index as usize,
+            );
+        }
+
+        rsrc::read_directory_tree(tree, |offset, entry| {
+            let symbol = relocations.get(&offset).ok_or_else(|| {
+                format!("ObjectFile: missing relocation for resource data entry at 0x{offset:x}")
+            })?;
+            let (section, value) = self.symbol(*symbol)?;
+            let start = value as usize + entry.offset_to_data as usize;
+            let data = try_slice(self.section_data(section)?, start, entry.size as usize)?;
+            Ok(data::bytes(mapping, data))
+        })
+    }
+
+    /// Read the attributes of the resource directory of this object, if present.
+    pub fn resource_directory(&self) -> Result<Option<DirectoryMetadata>> {
+        match self.resource_section() {
+            Some(index) => Ok(Some(rsrc::read_directory_metadata(
+                self.section_data(index)?,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Index of the section holding the resource directory tree.
+    fn resource_section(&self) -> Option<usize> {
+        self.sections
+            .iter()
+            .position(|section| matches!(section.name().as_str(), ".rsrc$01" | ".rsrc"))
+    }
+}
+
+/// Read resources contained in all COFF object members of an archive (`.lib`).
+/// Linker members, long name tables and import objects are skipped.
+pub fn read_archive(data: &[u8]) -> Result<Vec<ResourceEntry>> {
+    read_archive_with(data, None)
+}
+
+fn read_archive_with(data: &[u8], mapping: Option<&Mapping>) -> Result<Vec<ResourceEntry>> {
+    if !is_archive_file(data) {
+        return Err("read_archive(): missing archive signature".into());
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = IMAGE_ARCHIVE_START.len();
+    while offset < data.len() {
+        let header = try_slice(data, offset, IMAGE_ARCHIVE_MEMBER_HEADER_SIZE)?;
+        if &header[58..60] != b"`\n" {
+            return Err(format!("read_archive(): invalid member header at 0x{offset:x}").into());
+        }
+        let size = std::str::from_utf8(&header[48..58])
+            .ok()
+            .and_then(|size| size.trim_end().parse::<usize>().ok())
+            .ok_or_else(|| format!("read_archive(): invalid member size at 0x{offset:x}"))?;
+        let member = try_slice(data, offset + IMAGE_ARCHIVE_MEMBER_HEADER_SIZE, size)?;
+        if is_object_file(member) {
+            entries.extend(ObjectFile::parse(member)?.resources_with(mapping)?);
+        }
+        offset = align(offset + IMAGE_ARCHIVE_MEMBER_HEADER_SIZE + size, 2);
+    }
+
+    Ok(entries)
+}
+
+/// Read resources from a COFF object file or an archive (`.lib`).
+pub fn read_resources(data: &[u8]) -> Result<Vec<ResourceEntry>> {
+    read_resources_with(data, None)
+}
+
+/// Read resources from a COFF object file or an archive (`.lib`), borrowing
+/// their data from `mapping` if `data` is a part of its contents.
+pub(crate) fn read_resources_with(
+    data: &[u8],
+    mapping: Option<&Mapping>,
+) -> Result<Vec<ResourceEntry>> {
+    if is_archive_file(data) {
+        read_archive_with(data, mapping)
+    } else {
+        ObjectFile::parse(data)?.resources_with(mapping)
+    }
+}
+
+/// Pure-Rust read-only resource backend for COFF object files (`.obj`)
+/// and archives (`.lib`).
+#[derive(Debug)]
+pub struct CoffBackend {
+    storage: Storage,
+}
+
+impl CoffBackend {
+    /// Create a COFF backend bound to the supplied file.
+    pub fn new(file: &Path) -> CoffBackend {
+        CoffBackend::with_storage(Storage::File(file.to_path_buf()))
+    }
+
+    /// Create a COFF backend bound to the supplied storage.
+    pub fn with_storage(storage: Storage) -> CoffBackend {
+        CoffBackend { storage }
+    }
+
+    fn read_only(&self) -> Error {
+        format!(
+            "resource file '{}' is a COFF object or archive and can not be modified",
+            self.storage
+        )
+        .into()
+    }
+}
+
+impl ResourceBackend for CoffBackend {
+    fn load(&self) -> Result<Vec<ResourceEntry>> {
+        let mapping = self.storage.map()?;
+        read_resources_with(&mapping, Some(&mapping))
+    }
+
+    fn load_directory(&self) -> Result<Option<DirectoryMetadata>> {
+        let data = self.storage.read()?;
+        if is_archive_file(&data) {
+            return Ok(None);
+        }
+        ObjectFile::parse(&data)?.resource_directory()
+    }
+
+    fn begin(&mut self, _delete_existing_resources: bool) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn update(&mut self, _kind: &Id, _name: &Id, _lang: u16, _data: &[u8]) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn delete(&mut self, _kind: &Id, _name: &Id, _lang: u16) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn commit_to(&mut self, _storage: &Storage) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn discard(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
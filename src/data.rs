@@ -0,0 +1,190 @@
+//!
+//! Resource data borrowed from the contents of a resource file, which is
+//! memory-mapped so that resource data is only read when it is accessed.
+//!
+
+use crate::result::Result;
+use crate::utils::*;
+use memmap2::Mmap;
+use std::fmt;
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::sync::Arc;
+
+enum Contents {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+/// Contents of a resource file shared by the resources loaded from it.
+#[derive(Clone)]
+pub struct Mapping(Arc<Contents>);
+
+impl Mapping {
+    /// Memory-map the supplied file.
+    pub fn map(file: &Path) -> Result<Mapping> {
+        let file = std::fs::File::open(file)?;
+        // SAFETY: the mapping is read-only and this crate replaces resource
+        // files instead of modifying them in place (`Storage::write` and all
+        // other file writes go through `write_file_atomic`).
+        // Modifying a resource file by other means while resources loaded
+        // from it are alive is not supported.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Mapping(Arc::new(Contents::Mapped(mmap))))
+    }
+
+    /// Create a mapping holding the supplied data.
+    pub fn from_vec(data: Vec<u8>) -> Mapping {
+        Mapping(Arc::new(Contents::Owned(data)))
+    }
+
+    /// Returns `true` if the contents are memory-mapped from a file.
+    pub fn is_mapped(&self) -> bool {
+        matches!(*self.0, Contents::Mapped(_))
+    }
+
+    /// Resource data referring to `len` bytes at `offset`.
+    pub fn slice(&self, offset: usize, len: usize) -> Result<ResourceBytes> {
+        try_slice(self, offset, len)?;
+        Ok(ResourceBytes(Repr::Borrowed(
+            self.clone(),
+            offset..offset + len,
+        )))
+    }
+
+    /// Resource data referring to the supplied slice, which must be a part of
+    /// the contents of this mapping; other slices are copied.
+    pub fn data(&self, slice: &[u8]) -> ResourceBytes {
+        let base = self.as_ptr() as usize;
+        let start = slice.as_ptr() as usize;
+        if start >= base && start + slice.len() <= base + self.len() {
+            let offset = start - base;
+            ResourceBytes(Repr::Borrowed(self.clone(), offset..offset + slice.len()))
+        } else {
+            ResourceBytes::from(slice)
+        }
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match &*self.0 {
+            Contents::Mapped(mmap) => mmap,
+            Contents::Owned(data) => data,
+        }
+    }
+}
+
+impl fmt::Debug for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mapping")
+            .field("len", &self.len())
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
+
+/// Resource data supplied by `mapping` when present, otherwise copied.
+pub(crate) fn bytes(mapping: Option<&Mapping>, slice: &[u8]) -> ResourceBytes {
+    match mapping {
+        Some(mapping) => mapping.data(slice),
+        None => ResourceBytes::from(slice),
+    }
+}
+
+#[derive(Clone)]
+enum Repr {
+    Owned(Vec<u8>),
+    Borrowed(Mapping, Range<usize>),
+}
+
+/// Raw resource data, either borrowed from the contents of the resource file
+/// it was loaded from or owned. Borrowed data is copied once it is modified.
+#[derive(Clone)]
+pub struct ResourceBytes(Repr);
+
+impl ResourceBytes {
+    /// Returns `true` if the data refers to the contents of a resource file.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.0, Repr::Borrowed(..))
+    }
+
+    /// Mutable access to the data, copying borrowed data.
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let Repr::Borrowed(mapping, range) = &self.0 {
+            self.0 = Repr::Owned(mapping[range.clone()].to_vec());
+        }
+        match &mut self.0 {
+            Repr::Owned(data) => data,
+            Repr::Borrowed(..) => unreachable!(),
+        }
+    }
+
+    /// Convert the data into a vector, copying borrowed data.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.0 {
+            Repr::Owned(data) => data,
+            Repr::Borrowed(mapping, range) => mapping[range].to_vec(),
+        }
+    }
+
+    /// Shorten the data to `len` bytes.
+    pub fn truncate(&mut self, len: usize) {
+        match &mut self.0 {
+            Repr::Owned(data) => data.truncate(len),
+            Repr::Borrowed(_, range) => range.end = range.end.min(range.start + len),
+        }
+    }
+}
+
+impl Default for ResourceBytes {
+    fn default() -> Self {
+        ResourceBytes(Repr::Owned(Vec::new()))
+    }
+}
+
+impl Deref for ResourceBytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Owned(data) => data,
+            Repr::Borrowed(mapping, range) => &mapping[range.clone()],
+        }
+    }
+}
+
+impl AsRef<[u8]> for ResourceBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for ResourceBytes {
+    fn from(data: Vec<u8>) -> Self {
+        ResourceBytes(Repr::Owned(data))
+    }
+}
+
+impl From<&[u8]> for ResourceBytes {
+    fn from(data: &[u8]) -> Self {
+        ResourceBytes(Repr::Owned(data.to_vec()))
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> PartialEq<T> for ResourceBytes {
+    fn eq(&self, other: &T) -> bool {
+        **self == *other.as_ref()
+    }
+}
+
+impl Eq for ResourceBytes {}
+
+impl fmt::Debug for ResourceBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResourceBytes")
+            .field("len", &self.len())
+            .field("borrowed", &self.is_borrowed())
+            .finish()
+    }
+}
@@ -0,0 +1,189 @@
+//!
+//! Zero-terminated ANSI and UTF-16 encodings of strings and resource ids
+//! passed to the Win32 API. Encodings own their buffers: pointers obtained
+//! from an encoding remain valid for as long as the encoding is alive.
+//!
+
+use crate::id::Id;
+#[cfg(windows)]
+use std::ffi::OsStr;
+#[cfg(all(windows, feature = "win32"))]
+use windows::core::{PCSTR, PCWSTR};
+
+/// Returns `true` if the supplied resource id pointer represents an integer
+/// resource id (`IS_INTRESOURCE`).
+pub fn is_int_resource<T>(ptr: *const T) -> bool {
+    (ptr as usize) >> 16 == 0
+}
+
+/// Integer resource id represented as a pointer (`MAKEINTRESOURCE`).
+fn int_resource<T>(id: u16) -> *const T {
+    std::ptr::null::<T>().wrapping_byte_add(id as usize)
+}
+
+/// Zero-terminated ANSI string. Characters outside of Latin-1 are replaced by `?`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnsiString(Vec<u8>);
+
+impl AnsiString {
+    /// Encode the supplied string.
+    pub fn new(text: &str) -> AnsiString {
+        let mut data: Vec<u8> = text
+            .chars()
+            .map(|c| u8::try_from(c).unwrap_or(b'?'))
+            .collect();
+        data.push(0);
+        AnsiString(data)
+    }
+
+    /// Encoded string excluding the zero terminator.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..self.0.len() - 1]
+    }
+
+    /// Pointer to the zero-terminated string, valid while `self` is alive.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.0.as_ptr()
+    }
+
+    /// [`PCSTR`] referring to the string, valid while `self` is alive.
+    #[cfg(all(windows, feature = "win32"))]
+    pub fn as_pcstr(&self) -> PCSTR {
+        PCSTR::from_raw(self.as_ptr())
+    }
+}
+
+/// Zero-terminated UTF-16 string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WideString(Vec<u16>);
+
+impl WideString {
+    /// Encode the supplied string.
+    pub fn new(text: &str) -> WideString {
+        WideString(text.encode_utf16().chain(std::iter::once(0)).collect())
+    }
+
+    /// Encode the supplied OS string (such as a path), which is not required
+    /// to be valid Unicode.
+    #[cfg(windows)]
+    pub fn from_os_str(text: &OsStr) -> WideString {
+        use std::os::windows::ffi::OsStrExt;
+        WideString(text.encode_wide().chain(std::iter::once(0)).collect())
+    }
+
+    /// Encoded string excluding the zero terminator.
+    pub fn as_units(&self) -> &[u16] {
+        &self.0[..self.0.len() - 1]
+    }
+
+    /// Pointer to the zero-terminated string, valid while `self` is alive.
+    pub fn as_ptr(&self) -> *const u16 {
+        self.0.as_ptr()
+    }
+
+    /// [`PCWSTR`] referring to the string, valid while `self` is alive.
+    #[cfg(all(windows, feature = "win32"))]
+    pub fn as_pcwstr(&self) -> PCWSTR {
+        PCWSTR::from_raw(self.as_ptr())
+    }
+}
+
+/// Resource id encoded for the ANSI (`A`) Win32 functions, created by [`Id::to_ansi()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnsiId {
+    Integer(u16),
+    Text(AnsiString),
+}
+
+impl AnsiId {
+    /// Integer resource id or a pointer to the zero-terminated name,
+    /// valid while `self` is alive.
+    pub fn as_ptr(&self) -> *const u8 {
+        match self {
+            AnsiId::Integer(id) => int_resource(*id),
+            AnsiId::Text(text) => text.as_ptr(),
+        }
+    }
+
+    /// [`PCSTR`] referring to the resource id, valid while `self` is alive.
+    #[cfg(all(windows, feature = "win32"))]
+    pub fn as_pcstr(&self) -> PCSTR {
+        PCSTR::from_raw(self.as_ptr())
+    }
+}
+
+/// Resource id encoded for the Unicode (`W`) Win32 functions, created by [`Id::to_wide()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WideId {
+    Integer(u16),
+    Text(WideString),
+}
+
+impl WideId {
+    /// Integer resource id or a pointer to the zero-terminated name,
+    /// valid while `self` is alive.
+    pub fn as_ptr(&self) -> *const u16 {
+        match self {
+            WideId::Integer(id) => int_resource(*id),
+            WideId::Text(text) => text.as_ptr(),
+        }
+    }
+
+    /// [`PCWSTR`] referring to the resource id, valid while `self` is alive.
+    #[cfg(all(windows, feature = "win32"))]
+    pub fn as_pcwstr(&self) -> PCWSTR {
+        PCWSTR::from_raw(self.as_ptr())
+    }
+}
+
+impl Id {
+    /// Encode the id for the ANSI (`A`) Win32 functions.
+    pub fn to_ansi(&self) -> AnsiId {
+        match self {
+            Id::Integer(id) => AnsiId::Integer(*id),
+            Id::Text(text) => AnsiId::Text(AnsiString::new(text)),
+        }
+    }
+
+    /// Encode the id for the Unicode (`W`) Win32 functions.
+    pub fn to_wide(&self) -> WideId {
+        match self {
+            Id::Integer(id) => WideId::Integer(*id),
+            Id::Text(text) => WideId::Text(WideString::new(text)),
+        }
+    }
+
+    /// Decode a resource id supplied by the ANSI (`A`) Win32 functions.
+    ///
+    /// # Safety
+    ///
+    /// Unless it represents an integer resource id, `ptr` must point
+    /// to a valid zero-terminated string.
+    pub unsafe fn from_ansi_ptr(ptr: *const u8) -> Id {
+        if is_int_resource(ptr) {
+            return Id::Integer(ptr as usize as u16);
+        }
+        let text = std::ffi::CStr::from_ptr(ptr as *const std::ffi::c_char);
+        Id::Text(text.to_bytes().iter().map(|c| *c as char).collect())
+    }
+
+    /// Decode a resource id supplied by the Unicode (`W`) Win32 functions.
+    /// Unpaired surrogates are replaced by `U+FFFD`.
+    ///
+    /// # Safety
+    ///
+    /// Unless it represents an integer resource id, `ptr` must point
+    /// to a valid zero-terminated UTF-16 string.
+    pub unsafe fn from_wide_ptr(ptr: *const u16) -> Id {
+        if is_int_resource(ptr) {
+            return Id::Integer(ptr as usize as u16);
+        }
+        let mut len = 0;
+        while *ptr.add(len) != 0 {
+            len += 1;
+        }
+        Id::Text(String::from_utf16_lossy(std::slice::from_raw_parts(
+            ptr, len,
+        )))
+    }
+}
@@ -18,6 +18,10 @@ pub enum Error {
     #[error("{0}")]
     SerializerError(#[from] SerializerError),
 
+    #[error("{0}")]
+    IoError(#[from] std::io::Error),
+
+    #[cfg(all(windows, feature = "win32"))]
     #[error("{0}")]
     Win32Error(::windows::core::Error),
 }
@@ -40,6 +44,7 @@ impl From<FromUtf16Error> for Error {
     }
 }
 
+#[cfg(all(windows, feature = "win32"))]
 impl From<::windows::core::Error> for Error {
     fn from(e: ::windows::core::Error) -> Error {
         Error::Win32Error(e)
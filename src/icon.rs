@@ -0,0 +1,101 @@
+//!
+//! Icon group (`RT_GROUP_ICON`) resources and their conversion to `.ico` files.
+//!
+
+use crate::result::Result;
+use crate::utils::*;
+use manual_serializer::*;
+
+/// Byte size of the `GRPICONDIR` header
+pub const GRPICONDIR_SIZE: usize = 6;
+/// Byte size of the `GRPICONDIRENTRY` record
+pub const GRPICONDIRENTRY_SIZE: usize = 14;
+/// Byte size of the `ICONDIR` header of `.ico` files
+pub const ICONDIR_SIZE: usize = 6;
+/// Byte size of the `ICONDIRENTRY` record of `.ico` files
+pub const ICONDIRENTRY_SIZE: usize = 16;
+
+/// Image of an icon group (`GRPICONDIRENTRY`) referring to an `RT_ICON` resource.
+/// Cursor groups (`RT_GROUP_CURSOR`) share the record layout, with the
+/// dimensions and the color count stored differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconGroupEntry {
+    pub width: u8,
+    pub height: u8,
+    pub color_count: u8,
+    pub planes: u16,
+    pub bit_count: u16,
+    /// size of the image data
+    pub bytes_in_res: u32,
+    /// integer name of the `RT_ICON` resource holding the image
+    pub id: u16,
+}
+
+/// Icon group resource (`GRPICONDIR`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconGroup {
+    /// resource type: `1` for icons, `2` for cursors
+    pub kind: u16,
+    pub entries: Vec<IconGroupEntry>,
+}
+
+impl IconGroup {
+    /// Parse icon group resource data.
+    pub fn parse(data: &[u8]) -> Result<IconGroup> {
+        let header = try_slice(data, 0, GRPICONDIR_SIZE)?;
+        let kind = load_u16le(&header[2..4]);
+        let count = load_u16le(&header[4..6]) as usize;
+        let records = try_slice(data, GRPICONDIR_SIZE, count * GRPICONDIRENTRY_SIZE)?;
+        let entries = records
+            .chunks_exact(GRPICONDIRENTRY_SIZE)
+            .map(|record| IconGroupEntry {
+                width: record[0],
+                height: record[1],
+                color_count: record[2],
+                planes: load_u16le(&record[4..6]),
+                bit_count: load_u16le(&record[6..8]),
+                bytes_in_res: load_u32le(&record[8..12]),
+                id: load_u16le(&record[12..14]),
+            })
+            .collect();
+        Ok(IconGroup { kind, entries })
+    }
+
+    /// Byte size of the icon group resource
+    pub fn size(&self) -> usize {
+        GRPICONDIR_SIZE + self.entries.len() * GRPICONDIRENTRY_SIZE
+    }
+
+    /// Build an `.ico` file from the group. `icon` is called to obtain the
+    /// data of the `RT_ICON` resource with the supplied integer name.
+    pub fn to_ico(&self, icon: impl Fn(u16) -> Option<Vec<u8>>) -> Result<Vec<u8>> {
+        let mut images = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let mut data = icon(entry.id)
+                .ok_or_else(|| format!("IconGroup: missing icon resource #{}", entry.id))?;
+            data.truncate(entry.bytes_in_res as usize);
+            images.push(data);
+        }
+
+        let mut ico = vec![0u8; ICONDIR_SIZE];
+        store_u16le(&mut ico[2..4], self.kind);
+        store_u16le(&mut ico[4..6], self.entries.len() as u16);
+        let mut offset = ICONDIR_SIZE + self.entries.len() * ICONDIRENTRY_SIZE;
+        for (entry, data) in self.entries.iter().zip(images.iter()) {
+            let mut record = [0u8; ICONDIRENTRY_SIZE];
+            record[0] = entry.width;
+            record[1] = entry.height;
+            record[2] = entry.color_count;
+            store_u16le(&mut record[4..6], entry.planes);
+            store_u16le(&mut record[6..8], entry.bit_count);
+            store_u32le(&mut record[8..12], data.len() as u32);
+            store_u32le(&mut record[12..16], offset as u32);
+            ico.extend_from_slice(&record);
+            offset += data.len();
+        }
+        for data in images {
+            ico.extend_from_slice(&data);
+        }
+        Ok(ico)
+    }
+}
@@ -1,52 +1,136 @@
-use windows::core::PCSTR;
+use crate::error::Error;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+#[cfg(all(windows, feature = "win32"))]
+use windows::core::{PCSTR, PCWSTR};
 
 ///
 /// Id enum that contains a windows resource id representation.  Windows resource ids can be
 /// a pointer to a string or if the pointer value is below 0xffff the id represents an integer
-/// resource id.  [`Id`] encapsulates this representation into a Rust enum and provides `From`
-/// and `Into` trait implementations to interop with the Windows API.
+/// resource id.  [`Id`] encapsulates this representation into a Rust enum; [`Id::to_ansi()`]
+/// and [`Id::to_wide()`] encode it for the Windows API.
 ///
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+/// Like Windows resource names, text ids compare and hash case-insensitively.
+/// Ids are ordered as in the PE resource directory: text ids (sorted
+/// case-insensitively) precede integer ids (sorted in ascending order).
+/// Ids are displayed and parsed as `#123` for integer ids and `NAME` for text ids.
+/// Text ids starting with `#` do not round trip: like the Win32 functions, which
+/// treat the name `#123` as the integer id 123, parsing their display yields an
+/// integer id or an error.
+///
+#[derive(Debug, Clone)]
 pub enum Id {
     Integer(u16),
     Text(String),
 }
 
-/// Convert a string pointer to an `Id`
-impl From<PCSTR> for Id {
-    fn from(v: PCSTR) -> Self {
-        let pv = v.0 as usize;
-        if pv < 0xffff {
-            Id::Integer(pv as u16)
-        } else {
-            unsafe { Id::Text(v.display().to_string()) }
+/// UTF-16 code units of the uppercased text, used to compare text ids and to
+/// store resource names. Characters without a single-character uppercase
+/// mapping are kept as is.
+pub(crate) fn folded(text: &str) -> impl Iterator<Item = u16> + '_ {
+    text.chars()
+        .map(|c| {
+            let mut upper = c.to_uppercase();
+            match (upper.next(), upper.next()) {
+                (Some(upper), None) => upper,
+                _ => c,
+            }
+        })
+        .flat_map(|c| {
+            let mut buffer = [0u16; 2];
+            let len = c.encode_utf16(&mut buffer).len();
+            buffer.into_iter().take(len)
+        })
+}
+
+impl PartialEq for Id {
+    fn eq(&self, other: &Id) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Id {}
+
+impl Hash for Id {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Id::Integer(id) => {
+                state.write_u8(0);
+                id.hash(state);
+            }
+            Id::Text(text) => {
+                state.write_u8(1);
+                folded(text).for_each(|c| c.hash(state));
+            }
         }
     }
 }
 
-/// Convert a `u16` value to an `Id`
-impl From<u16> for Id {
-    fn from(v: u16) -> Self {
-        Id::Integer(v)
+impl Ord for Id {
+    fn cmp(&self, other: &Id) -> Ordering {
+        match (self, other) {
+            (Id::Text(a), Id::Text(b)) => folded(a).cmp(folded(b)),
+            (Id::Text(_), Id::Integer(_)) => Ordering::Less,
+            (Id::Integer(_), Id::Text(_)) => Ordering::Greater,
+            (Id::Integer(a), Id::Integer(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for Id {
+    fn partial_cmp(&self, other: &Id) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-/// Convert an `Id` to a zero-terminated string pointer or
-/// an integer resource representation.
-impl Into<PCSTR> for Id {
-    fn into(self) -> PCSTR {
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Id::Integer(id) => PCSTR(id as *const u8),
-            Id::Text(text) => PCSTR::from_raw(format!("{text}\0").as_ptr()),
+            Id::Integer(id) => write!(f, "#{id}"),
+            Id::Text(text) => write!(f, "{text}"),
         }
     }
 }
 
-impl Into<PCSTR> for &Id {
-    fn into(self) -> PCSTR {
-        match self {
-            Id::Integer(id) => PCSTR(*id as *const u8),
-            Id::Text(text) => PCSTR::from_raw(format!("{text}\0").as_ptr()),
+/// Parse `#123` as an integer id and any other string as a text id.
+/// Strings starting with `#` which are not a valid integer id are rejected.
+impl FromStr for Id {
+    type Err = Error;
+    fn from_str(s: &str) -> std::result::Result<Id, Error> {
+        if s.is_empty() {
+            return Err("Id: empty resource id".into());
+        }
+        match s.strip_prefix('#') {
+            Some(id) => id
+                .parse::<u16>()
+                .map(Id::Integer)
+                .map_err(|_| format!("Id: invalid integer resource id '{s}'").into()),
+            None => Ok(Id::Text(s.to_string())),
         }
     }
 }
+
+/// Convert a string pointer supplied by the ANSI Win32 functions to an `Id`
+#[cfg(all(windows, feature = "win32"))]
+impl From<PCSTR> for Id {
+    fn from(v: PCSTR) -> Self {
+        unsafe { Id::from_ansi_ptr(v.0) }
+    }
+}
+
+/// Convert a string pointer supplied by the Unicode Win32 functions to an `Id`
+#[cfg(all(windows, feature = "win32"))]
+impl From<PCWSTR> for Id {
+    fn from(v: PCWSTR) -> Self {
+        unsafe { Id::from_wide_ptr(v.0) }
+    }
+}
+
+/// Convert a `u16` value to an `Id`
+impl From<u16> for Id {
+    fn from(v: u16) -> Self {
+        Id::Integer(v)
+    }
+}
@@ -0,0 +1,191 @@
+//!
+//! Windows language identifiers (`LANGID`) and their mapping to BCP-47 locale names.
+//!
+
+use crate::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Primary language of language-neutral resources
+pub const LANG_NEUTRAL: u16 = 0x00;
+/// Primary language of the invariant locale
+pub const LANG_INVARIANT: u16 = 0x7f;
+/// Sublanguage of language-neutral resources
+pub const SUBLANG_NEUTRAL: u16 = 0x00;
+/// Default sublanguage of the primary language (user default with [`LANG_NEUTRAL`])
+pub const SUBLANG_DEFAULT: u16 = 0x01;
+/// System default sublanguage (with [`LANG_NEUTRAL`])
+pub const SUBLANG_SYS_DEFAULT: u16 = 0x02;
+
+/// Windows language identifier (`LANGID`) made of a 10-bit primary language
+/// and a 6-bit sublanguage, as stored in resource directories and `.res` files.
+///
+/// Language ids are displayed as BCP-47 locale names (`en-US`) if known and as
+/// hex values (`0x0409`) otherwise. Parsing accepts locale names (case-insensitive,
+/// `-` or `_` separated), hex values prefixed by `0x` and decimal values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LangId(pub u16);
+
+/// Known language ids with their BCP-47 locale names and display names
+const LOCALES: &[(u16, &str, &str)] = &[
+    (0x0401, "ar-SA", "Arabic (Saudi Arabia)"),
+    (0x0402, "bg-BG", "Bulgarian (Bulgaria)"),
+    (0x0403, "ca-ES", "Catalan (Spain)"),
+    (0x0404, "zh-Hant-TW", "Chinese (Traditional, Taiwan)"),
+    (0x0405, "cs-CZ", "Czech (Czechia)"),
+    (0x0406, "da-DK", "Danish (Denmark)"),
+    (0x0407, "de-DE", "German (Germany)"),
+    (0x0408, "el-GR", "Greek (Greece)"),
+    (0x0409, "en-US", "English (United States)"),
+    (0x040b, "fi-FI", "Finnish (Finland)"),
+    (0x040c, "fr-FR", "French (France)"),
+    (0x040d, "he-IL", "Hebrew (Israel)"),
+    (0x040e, "hu-HU", "Hungarian (Hungary)"),
+    (0x040f, "is-IS", "Icelandic (Iceland)"),
+    (0x0410, "it-IT", "Italian (Italy)"),
+    (0x0411, "ja-JP", "Japanese (Japan)"),
+    (0x0412, "ko-KR", "Korean (Korea)"),
+    (0x0413, "nl-NL", "Dutch (Netherlands)"),
+    (0x0414, "nb-NO", "Norwegian Bokmål (Norway)"),
+    (0x0415, "pl-PL", "Polish (Poland)"),
+    (0x0416, "pt-BR", "Portuguese (Brazil)"),
+    (0x0418, "ro-RO", "Romanian (Romania)"),
+    (0x0419, "ru-RU", "Russian (Russia)"),
+    (0x041a, "hr-HR", "Croatian (Croatia)"),
+    (0x041b, "sk-SK", "Slovak (Slovakia)"),
+    (0x041d, "sv-SE", "Swedish (Sweden)"),
+    (0x041e, "th-TH", "Thai (Thailand)"),
+    (0x041f, "tr-TR", "Turkish (Türkiye)"),
+    (0x0422, "uk-UA", "Ukrainian (Ukraine)"),
+    (0x0424, "sl-SI", "Slovenian (Slovenia)"),
+    (0x0425, "et-EE", "Estonian (Estonia)"),
+    (0x0426, "lv-LV", "Latvian (Latvia)"),
+    (0x0427, "lt-LT", "Lithuanian (Lithuania)"),
+    (0x042a, "vi-VN", "Vietnamese (Vietnam)"),
+    (0x0439, "hi-IN", "Hindi (India)"),
+    (0x0804, "zh-Hans-CN", "Chinese (Simplified, China)"),
+    (0x0807, "de-CH", "German (Switzerland)"),
+    (0x0809, "en-GB", "English (United Kingdom)"),
+    (0x080a, "es-MX", "Spanish (Mexico)"),
+    (0x080c, "fr-BE", "French (Belgium)"),
+    (0x0810, "it-CH", "Italian (Switzerland)"),
+    (0x0813, "nl-BE", "Dutch (Belgium)"),
+    (0x0814, "nn-NO", "Norwegian Nynorsk (Norway)"),
+    (0x0816, "pt-PT", "Portuguese (Portugal)"),
+    (0x0c04, "zh-Hant-HK", "Chinese (Traditional, Hong Kong SAR)"),
+    (0x0c07, "de-AT", "German (Austria)"),
+    (0x0c09, "en-AU", "English (Australia)"),
+    (0x0c0a, "es-ES", "Spanish (Spain)"),
+    (0x0c0c, "fr-CA", "French (Canada)"),
+    (0x1004, "zh-Hans-SG", "Chinese (Simplified, Singapore)"),
+    (0x1009, "en-CA", "English (Canada)"),
+    (0x100c, "fr-CH", "French (Switzerland)"),
+    (0x1409, "en-NZ", "English (New Zealand)"),
+    (0x1809, "en-IE", "English (Ireland)"),
+    (0x4009, "en-IN", "English (India)"),
+];
+
+/// Locale names accepted when parsing in addition to the names of [`LOCALES`]
+const ALIASES: &[(&str, u16)] = &[
+    ("zh-TW", 0x0404),
+    ("zh-CN", 0x0804),
+    ("zh-HK", 0x0c04),
+    ("zh-SG", 0x1004),
+    ("no-NO", 0x0414),
+];
+
+impl LangId {
+    /// Language-neutral resources (`MAKELANGID(LANG_NEUTRAL, SUBLANG_NEUTRAL)`)
+    pub const NEUTRAL: LangId = LangId::new(LANG_NEUTRAL, SUBLANG_NEUTRAL);
+    /// Invariant locale (`MAKELANGID(LANG_INVARIANT, SUBLANG_NEUTRAL)`)
+    pub const INVARIANT: LangId = LangId::new(LANG_INVARIANT, SUBLANG_NEUTRAL);
+    /// English (United States)
+    pub const EN_US: LangId = LangId(0x0409);
+
+    /// Language id made of the supplied primary language and sublanguage (`MAKELANGID`).
+    pub const fn new(primary: u16, sub: u16) -> LangId {
+        LangId((sub << 10) | (primary & 0x3ff))
+    }
+
+    /// Primary language (`PRIMARYLANGID`)
+    pub const fn primary(&self) -> u16 {
+        self.0 & 0x3ff
+    }
+
+    /// Sublanguage (`SUBLANGID`)
+    pub const fn sub(&self) -> u16 {
+        self.0 >> 10
+    }
+
+    /// BCP-47 locale name of known language ids, e.g. `en-US`.
+    pub fn tag(&self) -> Option<&'static str> {
+        LOCALES
+            .iter()
+            .find(|(id, _, _)| *id == self.0)
+            .map(|(_, tag, _)| *tag)
+    }
+
+    /// English display name of known language ids, e.g. `English (United States)`.
+    pub fn display_name(&self) -> Option<&'static str> {
+        let name = match (self.primary(), self.sub()) {
+            (LANG_NEUTRAL, SUBLANG_NEUTRAL) => "Language Neutral",
+            (LANG_NEUTRAL, SUBLANG_DEFAULT) => "User Default Language",
+            (LANG_NEUTRAL, SUBLANG_SYS_DEFAULT) => "System Default Language",
+            (LANG_INVARIANT, SUBLANG_NEUTRAL) => "Invariant Language",
+            _ => LOCALES
+                .iter()
+                .find(|(id, _, _)| *id == self.0)
+                .map(|(_, _, name)| *name)?,
+        };
+        Some(name)
+    }
+
+    /// Language id of the supplied BCP-47 locale name (case-insensitive,
+    /// `-` or `_` separated).
+    pub fn from_tag(tag: &str) -> Option<LangId> {
+        let tag = tag.replace('_', "-");
+        LOCALES
+            .iter()
+            .map(|(id, name, _)| (*name, *id))
+            .chain(ALIASES.iter().copied())
+            .find(|(name, _)| name.eq_ignore_ascii_case(&tag))
+            .map(|(_, id)| LangId(id))
+    }
+}
+
+impl From<u16> for LangId {
+    fn from(id: u16) -> LangId {
+        LangId(id)
+    }
+}
+
+impl From<LangId> for u16 {
+    fn from(id: LangId) -> u16 {
+        id.0
+    }
+}
+
+impl fmt::Display for LangId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.tag() {
+            Some(tag) => write!(f, "{tag}"),
+            None => write!(f, "0x{:04x}", self.0),
+        }
+    }
+}
+
+impl FromStr for LangId {
+    type Err = Error;
+    fn from_str(s: &str) -> std::result::Result<LangId, Error> {
+        let invalid = || Error::from(format!("LangId: unknown language '{s}'"));
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            return u16::from_str_radix(hex, 16)
+                .map(LangId)
+                .map_err(|_| invalid());
+        }
+        if !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit()) {
+            return s.parse::<u16>().map(LangId).map_err(|_| invalid());
+        }
+        LangId::from_tag(s).ok_or_else(invalid)
+    }
+}
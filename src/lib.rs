@@ -11,12 +11,51 @@ which is useful to modify application manifests. Loaded resources are
 available as raw `Vec<u8>` data, useful to modify bitmaps and icons.
 
 Please note that all operations performed on the opened resource file are accumulated and are then "flushed" to the file when the file is closed
-using the `close()` function. This is due to the behavior of the underlying Win32 API ([UpdateResource](https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-updateresourcea)) functionality used by this crate.
+using the `close()` function. This is due to the behavior of the underlying Win32 API ([UpdateResource](https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-updateresourcew)) functionality used by this crate.
+Changes staged until then can be listed using `pending_changes()`, individually discarded
+and verified without modifying the file using `dry_run()`.
+Alternatively, `save_as()` writes the changes to a separate file leaving the original file unchanged;
+the resource list is then reloaded from the original file.
+Changes are written to a temporary file that replaces the original file once complete, so a failed
+update (reported as an error by `close()`) leaves the file intact; `Resources::set_backup()` keeps a `.bak` copy.
+
+Resource files are accessed through a resource backend selected based on the file
+type or explicitly using `Resources::with_backend()`. On Windows, executables are
+handled by the Win32 API (`win32` feature, enabled by default). On other platforms,
+the PE resource section is parsed and rebuilt by a pure-Rust implementation.
+Compiled resource (`.res`) files are handled natively on all platforms, COFF object
+(`.obj`) files, static libraries (`.lib`) and 16-bit NE executables including `.icl`
+icon libraries can be loaded but not modified. Icon groups can be extracted as `.ico`
+files using `Resources::get_icon_group()`.
+Resource files held in memory can be processed without accessing the filesystem
+using `Resources::from_bytes()` and `Resources::to_bytes()`.
+The pure-Rust backends memory-map resource files: loaded resource data (`ResourceBytes`)
+refers to the file contents and is only copied once it is replaced, so listing the resources
+of large files is cheap.
+
+Modifying resources invalidates the Authenticode signature of signed executables.
+Such files are refused by default; use `Resources::set_signature_policy()` to strip
+the certificate table (so the file can be re-signed) or to preserve it.
+Loaded resources can be stored as a `.res` file (`Resources::save_res()`) or as a
+COFF object file ready for linking (`Resources::save_coff()`), replacing `cvtres.exe`.
+
+Resource attributes (the code page and the `.res` memory flags, versions and characteristics)
+are available through `Resource::metadata()` and retained when resources are replaced; the
+attributes of the resource directory (time stamp and version) through `Resources::directory()`.
+`Resources::set_deterministic()` zeroes the resource directory time stamp for reproducible builds.
+
+Resource types are identified by the `resource_type` constants or the `ResourceType` enum,
+which converts to and from `Id` and parses names such as `RT_GROUP_ICON`, `ICON` or `#14`.
+As on Windows, resource names (`Id::Text`) are case-insensitive and are stored uppercased, as
+by resource compilers. Names may contain any Unicode characters; they are stored as
+UTF-16 and the Win32 backend uses the Unicode (`W`) functions.
+Resource languages can be inspected as `LangId` values (`Resource::lang_id()`), which expose the
+primary language and sublanguage and convert to and from BCP-47 locale names such as `en-US`.
 
 ### Example
 
 #### Load and update a resource
-```rust
+```rust,ignore
 let mut resources = Resources::new(&Path::new("myfile.exe"));
 resources.load().expect("Unable to load resources");
 resources.open().expect("Unable to open resource file for updates");
@@ -39,12 +78,12 @@ resources.get_version_info()?.expect("Unable to get version info")
     .remove_string("SomeExistingString")
     .update()?;
 
-resources.close();
+resources.close()?;
 ```
 
 #### Create a new resource
 
-```rust
+```rust,ignore
 let res = Resource::new(
     &resources,
     resource_type::ICON.into(),
@@ -55,14 +94,35 @@ let res = Resource::new(
 res.update()?;
 */
 
+pub mod backend;
+pub mod coff;
+mod data;
+mod encoding;
 mod error;
+pub mod icon;
 mod id;
+pub mod lang;
+pub mod ne;
+pub mod pe;
+pub mod res;
 mod resources;
 mod result;
+pub mod rsrc;
+mod session;
 mod utils;
 mod version;
+#[cfg(all(windows, feature = "win32"))]
+pub mod win32;
 
+pub use backend::{BackendKind, ResourceBackend, Storage};
+pub use coff::Machine;
+pub use data::{Mapping, ResourceBytes};
+pub use encoding::{is_int_resource, AnsiId, AnsiString, WideId, WideString};
 pub use error::*;
 pub use id::*;
+pub use lang::LangId;
+pub use pe::SignaturePolicy;
 pub use resources::*;
+pub use rsrc::DirectoryMetadata;
+pub use session::*;
 pub use version::*;
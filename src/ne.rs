@@ -0,0 +1,247 @@
+//!
+//! 16-bit New Executable (NE) resource table reader for Win16 executables
+//! and `.icl` icon libraries.
+//!
+
+use crate::backend::{ResourceBackend, Storage};
+use crate::data::{self, Mapping};
+use crate::error::Error;
+use crate::icon::IconGroup;
+use crate::id::Id;
+use crate::pe::IMAGE_DOS_SIGNATURE;
+use crate::resources::{resource_type, ResourceEntry, ResourceMetadata};
+use crate::result::Result;
+use crate::utils::*;
+use manual_serializer::*;
+use std::path::Path;
+
+/// NE header signature (`NE`)
+pub const IMAGE_OS2_SIGNATURE: u16 = 0x454e;
+/// Byte size of the NE header
+pub const IMAGE_OS2_HEADER_SIZE: usize = 0x40;
+/// Byte size of the `TYPEINFO` record (excluding the `NAMEINFO` records)
+pub const TYPEINFO_SIZE: usize = 8;
+/// Byte size of the `NAMEINFO` record
+pub const NAMEINFO_SIZE: usize = 12;
+/// Flag marking integer resource type and name ids
+const NE_INTEGER_ID: u16 = 0x8000;
+
+/// File offset of the header following the DOS header (`e_lfanew`)
+pub(crate) fn new_header_offset(data: &[u8]) -> Option<usize> {
+    if data.len() < 0x40 || load_u16le(&data[0..2]) != IMAGE_DOS_SIGNATURE {
+        return None;
+    }
+    Some(load_u32le(&data[0x3c..0x40]) as usize)
+}
+
+/// Returns `true` if the supplied data is an NE image.
+pub fn is_ne_image(data: &[u8]) -> bool {
+    new_header_offset(data)
+        .and_then(|offset| try_slice(data, offset, 2).ok())
+        .map(|signature| load_u16le(signature) == IMAGE_OS2_SIGNATURE)
+        .unwrap_or(false)
+}
+
+/// Resource record of the NE resource table (`NAMEINFO`).
+#[derive(Debug, Clone)]
+pub struct NeResource {
+    /// resource type
+    pub kind: Id,
+    /// resource name
+    pub name: Id,
+    /// file offset of the resource data
+    pub offset: usize,
+    /// size of the resource data, rounded up to the resource alignment
+    pub length: usize,
+    /// resource flags (`MOVEABLE`, `PURE`, `PRELOAD`)
+    pub flags: u16,
+}
+
+/// Parsed view of an NE image referring to the underlying file data.
+#[derive(Debug, Clone)]
+pub struct NeImage<'data> {
+    data: &'data [u8],
+    /// resource alignment shift count (`rscAlignShift`)
+    pub align_shift: u16,
+    pub resources: Vec<NeResource>,
+}
+
+impl<'data> NeImage<'data> {
+    /// Parse the NE header and the resource table of the supplied image data.
+    pub fn parse(data: &'data [u8]) -> Result<NeImage<'data>> {
+        let ne_offset = new_header_offset(data)
+            .ok_or_else(|| Error::from("NeImage::parse(): missing DOS (MZ) signature"))?;
+        let header = try_slice(data, ne_offset, IMAGE_OS2_HEADER_SIZE)?;
+        if load_u16le(&header[0..2]) != IMAGE_OS2_SIGNATURE {
+            return Err("NeImage::parse(): missing NE signature".into());
+        }
+        let resource_table = load_u16le(&header[0x24..0x26]) as usize;
+        let resident_name_table = load_u16le(&header[0x26..0x28]) as usize;
+        if resource_table == 0 || resource_table == resident_name_table {
+            return Ok(NeImage {
+                data,
+                align_shift: 0,
+                resources: Vec::new(),
+            });
+        }
+
+        let table = ne_offset + resource_table;
+        let align_shift = load_u16le(try_slice(data, table, 2)?);
+        if align_shift > 15 {
+            return Err(format!("NeImage::parse(): invalid alignment shift {align_shift}").into());
+        }
+
+        let mut resources = Vec::new();
+        let mut offset = table + 2;
+        loop {
+            let type_id = load_u16le(try_slice(data, offset, 2)?);
+            if type_id == 0 {
+                break;
+            }
+            let typeinfo = try_slice(data, offset, TYPEINFO_SIZE)?;
+            let count = load_u16le(&typeinfo[2..4]) as usize;
+            let kind = read_id(data, table, type_id)?;
+            offset += TYPEINFO_SIZE;
+            let records = try_slice(data, offset, count * NAMEINFO_SIZE)?;
+            for record in records.chunks_exact(NAMEINFO_SIZE) {
+                resources.push(NeResource {
+                    kind: kind.clone(),
+                    name: read_id(data, table, load_u16le(&record[6..8]))?,
+                    offset: (load_u16le(&record[0..2]) as usize) << align_shift,
+                    length: (load_u16le(&record[2..4]) as usize) << align_shift,
+                    flags: load_u16le(&record[4..6]),
+                });
+            }
+            offset += records.len();
+        }
+
+        Ok(NeImage {
+            data,
+            align_shift,
+            resources,
+        })
+    }
+
+    /// Read the resources contained in the image. The NE resource table
+    /// records sizes in alignment units; the data of icon and cursor
+    /// resources and their groups is trimmed to the exact size.
+    pub fn resources(&self) -> Result<Vec<ResourceEntry>> {
+        self.resources_with(None)
+    }
+
+    /// Read the resources contained in the image, borrowing their data from
+    /// `mapping` if the image is parsed from its contents.
+    pub(crate) fn resources_with(&self, mapping: Option<&Mapping>) -> Result<Vec<ResourceEntry>> {
+        let mut entries = self
+            .resources
+            .iter()
+            .map(|resource| {
+                let data = try_slice(self.data, resource.offset, resource.length)?;
+                Ok(ResourceEntry {
+                    kind: resource.kind.clone(),
+                    name: resource.name.clone(),
+                    lang: 0,
+                    data: data::bytes(mapping, data),
+                    metadata: ResourceMetadata::default(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // image sizes recorded by icon and cursor groups
+        let mut sizes = Vec::new();
+        for entry in entries.iter_mut() {
+            let kind = if entry.kind == resource_type::GROUP_ICON {
+                resource_type::ICON
+            } else if entry.kind == resource_type::GROUP_CURSOR {
+                resource_type::CURSOR
+            } else {
+                continue;
+            };
+            let group = IconGroup::parse(&entry.data)?;
+            entry.data.truncate(group.size());
+            for image in group.entries {
+                sizes.push((kind.clone(), Id::Integer(image.id), image.bytes_in_res));
+            }
+        }
+        for entry in entries.iter_mut() {
+            if let Some((_, _, size)) = sizes
+                .iter()
+                .find(|(kind, name, _)| kind == &entry.kind && name == &entry.name)
+            {
+                entry.data.truncate(*size as usize);
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Read a resource type or name id: an integer id if the high bit is set,
+/// otherwise the offset of a length-prefixed string relative to the resource table.
+fn read_id(data: &[u8], table: usize, id: u16) -> Result<Id> {
+    if id & NE_INTEGER_ID != 0 {
+        return Ok(Id::Integer(id & !NE_INTEGER_ID));
+    }
+    let offset = table + id as usize;
+    let len = try_slice(data, offset, 1)?[0] as usize;
+    let text = try_slice(data, offset + 1, len)?;
+    Ok(Id::Text(text.iter().map(|c| *c as char).collect()))
+}
+
+/// Pure-Rust read-only resource backend for NE images (Win16 executables
+/// and `.icl` icon libraries).
+#[derive(Debug)]
+pub struct NeBackend {
+    storage: Storage,
+}
+
+impl NeBackend {
+    /// Create an NE backend bound to the supplied file.
+    pub fn new(file: &Path) -> NeBackend {
+        NeBackend::with_storage(Storage::File(file.to_path_buf()))
+    }
+
+    /// Create an NE backend bound to the supplied storage.
+    pub fn with_storage(storage: Storage) -> NeBackend {
+        NeBackend { storage }
+    }
+
+    fn read_only(&self) -> Error {
+        format!(
+            "resource file '{}' is an NE image and can not be modified",
+            self.storage
+        )
+        .into()
+    }
+}
+
+impl ResourceBackend for NeBackend {
+    fn load(&self) -> Result<Vec<ResourceEntry>> {
+        let mapping = self.storage.map()?;
+        NeImage::parse(&mapping)?.resources_with(Some(&mapping))
+    }
+
+    fn begin(&mut self, _delete_existing_resources: bool) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn update(&mut self, _kind: &Id, _name: &Id, _lang: u16, _data: &[u8]) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn delete(&mut self, _kind: &Id, _name: &Id, _lang: u16) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn commit_to(&mut self, _storage: &Storage) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn discard(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
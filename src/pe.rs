@@ -0,0 +1,1056 @@
+//!
+//! Portable Executable (PE) image parsing.
+//!
+
+use crate::backend::{ResourceBackend, Storage};
+use crate::data::{self, Mapping};
+use crate::error::Error;
+use crate::id::Id;
+use crate::resources::{ResourceEntry, ResourceMetadata};
+use crate::result::Result;
+use crate::rsrc::{self, DirectoryMetadata};
+use crate::utils::*;
+use manual_serializer::*;
+use std::path::Path;
+
+/// `MZ` signature of the DOS header
+pub const IMAGE_DOS_SIGNATURE: u16 = 0x5a4d;
+/// `PE\0\0` signature of the NT headers
+pub const IMAGE_NT_SIGNATURE: u32 = 0x0000_4550;
+/// Optional header magic of 32-bit (PE32) images
+pub const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10b;
+/// Optional header magic of 64-bit (PE32+) images
+pub const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20b;
+/// Index of the resource table within the optional header data directories
+pub const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+/// Index of the certificate table within the optional header data directories
+pub const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+/// Index of the base relocation table within the optional header data directories
+pub const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+
+/// Section contains initialized data
+pub const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+/// Section can be read
+pub const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+
+/// Byte size of the `IMAGE_FILE_HEADER` structure
+pub const IMAGE_FILE_HEADER_SIZE: usize = 20;
+/// Byte size of the `IMAGE_SECTION_HEADER` structure
+pub const IMAGE_SECTION_HEADER_SIZE: usize = 40;
+/// Byte size of the `IMAGE_DATA_DIRECTORY` structure
+pub const IMAGE_DATA_DIRECTORY_SIZE: usize = 8;
+/// Images with a section alignment below the page size map sections
+/// at file offsets equal to their RVAs
+const PAGE_SIZE: u32 = 0x1000;
+/// Alignment of the certificate table and its `WIN_CERTIFICATE` entries
+pub const WIN_CERTIFICATE_ALIGNMENT: usize = 8;
+/// Offset of the `CheckSum` field within the optional header
+const CHECKSUM_OFFSET: usize = 64;
+
+/// Returns `true` if the supplied data starts with the DOS (`MZ`) signature.
+pub fn is_pe_image(data: &[u8]) -> bool {
+    data.len() >= 2 && load_u16le(&data[0..2]) == IMAGE_DOS_SIGNATURE
+}
+
+/// Handling of the Authenticode signature (certificate table) when
+/// modifying resources of a signed image. Any modification invalidates
+/// the signature, so the image must be re-signed afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignaturePolicy {
+    /// Refuse to open signed images for modification
+    #[default]
+    Refuse,
+    /// Remove the certificate table so the image can be re-signed
+    Strip,
+    /// Retain the (now invalid) certificate table, relocating it past
+    /// the end of the modified image
+    Preserve,
+}
+
+/// Data following the raw data of the last section of an image.
+struct Tail<'data> {
+    /// data appended past the last section (excluding the certificate table)
+    overlay: &'data [u8],
+    /// certificate table (`IMAGE_DIRECTORY_ENTRY_SECURITY`) located past the last section
+    certificate: Option<&'data [u8]>,
+    /// data appended past the certificate table
+    trailing: &'data [u8],
+}
+
+/// Entry of the optional header data directory table (`IMAGE_DATA_DIRECTORY`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DataDirectory {
+    pub virtual_address: u32,
+    pub size: u32,
+}
+
+/// PE section header (`IMAGE_SECTION_HEADER`).
+#[derive(Debug, Clone, Default)]
+pub struct Section {
+    pub name: [u8; 8],
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub pointer_to_relocations: u32,
+    pub pointer_to_linenumbers: u32,
+    pub number_of_relocations: u16,
+    pub number_of_linenumbers: u16,
+    pub characteristics: u32,
+}
+
+impl Section {
+    /// Section name with trailing zero padding removed
+    pub fn name(&self) -> String {
+        let len = self.name.iter().position(|c| *c == 0).unwrap_or(8);
+        String::from_utf8_lossy(&self.name[..len]).to_string()
+    }
+
+    /// Returns `true` if the supplied RVA falls within this section
+    pub fn contains_rva(&self, rva: u32) -> bool {
+        let size = self.virtual_size.max(self.size_of_raw_data);
+        rva >= self.virtual_address && rva - self.virtual_address < size
+    }
+}
+
+impl Section {
+    /// Store the `IMAGE_SECTION_HEADER` representation in the supplied buffer
+    fn store(&self, dest: &mut [u8]) {
+        dest[0..8].copy_from_slice(&self.name);
+        store_u32le(&mut dest[8..], self.virtual_size);
+        store_u32le(&mut dest[12..], self.virtual_address);
+        store_u32le(&mut dest[16..], self.size_of_raw_data);
+        store_u32le(&mut dest[20..], self.pointer_to_raw_data);
+        store_u32le(&mut dest[24..], self.pointer_to_relocations);
+        store_u32le(&mut dest[28..], self.pointer_to_linenumbers);
+        store_u16le(&mut dest[32..], self.number_of_relocations);
+        store_u16le(&mut dest[34..], self.number_of_linenumbers);
+        store_u32le(&mut dest[36..], self.characteristics);
+    }
+
+    /// Virtual end of the section (not aligned)
+    fn virtual_end(&self) -> u32 {
+        let size = if self.virtual_size != 0 {
+            self.virtual_size
+        } else {
+            self.size_of_raw_data
+        };
+        self.virtual_address + size
+    }
+
+    /// File offset of the end of section raw data
+    fn raw_end(&self) -> usize {
+        self.pointer_to_raw_data as usize + self.size_of_raw_data as usize
+    }
+}
+
+impl TryDeserialize for Section {
+    type Error = Error;
+    fn try_deserialize(src: &mut Deserializer) -> Result<Section> {
+        let mut name = [0u8; 8];
+        name.copy_from_slice(&src.try_load_u8_vec(8)?);
+        Ok(Section {
+            name,
+            virtual_size: src.try_load_u32le()?,
+            virtual_address: src.try_load_u32le()?,
+            size_of_raw_data: src.try_load_u32le()?,
+            pointer_to_raw_data: src.try_load_u32le()?,
+            pointer_to_relocations: src.try_load_u32le()?,
+            pointer_to_linenumbers: src.try_load_u32le()?,
+            number_of_relocations: src.try_load_u16le()?,
+            number_of_linenumbers: src.try_load_u16le()?,
+            characteristics: src.try_load_u32le()?,
+        })
+    }
+}
+
+/// Parsed view of a PE image (`.exe`, `.dll` etc.) referring
+/// to the underlying file data.
+#[derive(Debug, Clone)]
+pub struct PeImage<'data> {
+    data: &'data [u8],
+    /// `IMAGE_FILE_HEADER::Machine`
+    pub machine: u16,
+    /// optional header magic (PE32 or PE32+)
+    pub magic: u16,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub checksum: u32,
+    pub data_directories: Vec<DataDirectory>,
+    pub sections: Vec<Section>,
+    /// `NumberOfRvaAndSizes` as stored in the optional header
+    number_of_rva_and_sizes: u32,
+    /// file offset of the `IMAGE_FILE_HEADER`
+    file_header_offset: usize,
+    /// file offset of the optional header
+    optional_header_offset: usize,
+    /// file offset of the data directory table
+    data_directories_offset: usize,
+    /// file offset of the first section header
+    section_table_offset: usize,
+    /// end of the section table as stored in the file
+    headers_end: usize,
+}
+
+impl<'data> PeImage<'data> {
+    /// Parse PE headers and the section table of the supplied image data.
+    pub fn parse(data: &'data [u8]) -> Result<PeImage<'data>> {
+        let mut src = Deserializer::new(try_slice(data, 0, 64)?);
+        if src.try_load_u16le()? != IMAGE_DOS_SIGNATURE {
+            return Err("PeImage::parse(): missing DOS (MZ) signature".into());
+        }
+        src.try_set_cursor(0x3c)?;
+        let pe_offset = src.try_load_u32le()? as usize;
+
+        let mut src = Deserializer::new(try_slice(data, pe_offset, 4 + IMAGE_FILE_HEADER_SIZE)?);
+        if src.try_load_u32le()? != IMAGE_NT_SIGNATURE {
+            return Err("PeImage::parse(): missing PE signature".into());
+        }
+        let file_header_offset = pe_offset + 4;
+        let machine = src.try_load_u16le()?;
+        let number_of_sections = src.try_load_u16le()? as usize;
+        src.try_offset(12)?;
+        let size_of_optional_header = src.try_load_u16le()? as usize;
+
+        let optional_header_offset = file_header_offset + IMAGE_FILE_HEADER_SIZE;
+        let optional_header = try_slice(data, optional_header_offset, size_of_optional_header)?;
+        let mut src = Deserializer::new(try_slice(optional_header, 0, 2)?);
+        let magic = src.try_load_u16le()?;
+        // offset of NumberOfRvaAndSizes, which is followed by the data directories
+        let rva_and_sizes_offset = match magic {
+            IMAGE_NT_OPTIONAL_HDR32_MAGIC => 92,
+            IMAGE_NT_OPTIONAL_HDR64_MAGIC => 108,
+            _ => {
+                return Err(
+                    format!("PeImage::parse(): unknown optional header magic 0x{magic:x}").into(),
+                )
+            }
+        };
+
+        let mut src = Deserializer::new(try_slice(optional_header, 0, rva_and_sizes_offset + 4)?);
+        src.try_set_cursor(32)?;
+        let section_alignment = src.try_load_u32le()?;
+        let file_alignment = src.try_load_u32le()?;
+        src.try_set_cursor(56)?;
+        let size_of_image = src.try_load_u32le()?;
+        let size_of_headers = src.try_load_u32le()?;
+        let checksum = src.try_load_u32le()?;
+        src.try_set_cursor(rva_and_sizes_offset)?;
+        let number_of_rva_and_sizes = src.try_load_u32le()?;
+
+        let available =
+            (size_of_optional_header - rva_and_sizes_offset - 4) / IMAGE_DATA_DIRECTORY_SIZE;
+        let count = (number_of_rva_and_sizes as usize).min(available);
+        let mut src = Deserializer::new(try_slice(
+            optional_header,
+            rva_and_sizes_offset + 4,
+            count * IMAGE_DATA_DIRECTORY_SIZE,
+        )?);
+        let mut data_directories = Vec::with_capacity(count);
+        for _ in 0..count {
+            data_directories.push(DataDirectory {
+                virtual_address: src.try_load_u32le()?,
+                size: src.try_load_u32le()?,
+            });
+        }
+
+        let data_directories_offset = optional_header_offset + rva_and_sizes_offset + 4;
+        let section_table_offset = optional_header_offset + size_of_optional_header;
+        let mut src = Deserializer::new(try_slice(
+            data,
+            section_table_offset,
+            number_of_sections * IMAGE_SECTION_HEADER_SIZE,
+        )?);
+        let mut sections = Vec::with_capacity(number_of_sections);
+        for _ in 0..number_of_sections {
+            sections.push(src.try_load()?);
+        }
+
+        Ok(PeImage {
+            data,
+            machine,
+            magic,
+            section_alignment,
+            file_alignment,
+            size_of_image,
+            size_of_headers,
+            checksum,
+            data_directories,
+            sections,
+            number_of_rva_and_sizes,
+            file_header_offset,
+            optional_header_offset,
+            data_directories_offset,
+            section_table_offset,
+            headers_end: section_table_offset + number_of_sections * IMAGE_SECTION_HEADER_SIZE,
+        })
+    }
+
+    /// Raw image data
+    pub fn data(&self) -> &'data [u8] {
+        self.data
+    }
+
+    /// Returns `true` for PE32+ (64-bit) images
+    pub fn is_64bit(&self) -> bool {
+        self.magic == IMAGE_NT_OPTIONAL_HDR64_MAGIC
+    }
+
+    /// Returns the data directory at `index` if present and populated
+    pub fn data_directory(&self, index: usize) -> Option<DataDirectory> {
+        self.data_directories
+            .get(index)
+            .filter(|dir| dir.virtual_address != 0)
+            .copied()
+    }
+
+    /// Locate the section containing the supplied RVA
+    pub fn section_for_rva(&self, rva: u32) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|section| section.contains_rva(rva))
+    }
+
+    /// Obtain `len` bytes of image data located at the supplied RVA.
+    pub fn slice_at_rva(&self, rva: u32, len: usize) -> Result<&'data [u8]> {
+        let section = self
+            .section_for_rva(rva)
+            .ok_or_else(|| format!("PeImage: RVA 0x{rva:x} is outside of any section"))?;
+        let delta = (rva - section.virtual_address) as usize;
+        if delta + len > section.size_of_raw_data as usize {
+            return Err(format!(
+                "PeImage: {len} bytes at RVA 0x{rva:x} exceed raw data of section '{}'",
+                section.name()
+            )
+            .into());
+        }
+        try_slice(self.data, section.pointer_to_raw_data as usize + delta, len)
+    }
+
+    /// Resource directory tree extending from the start of the resource
+    /// directory to the end of the section containing it.
+    fn resource_tree(&self) -> Result<Option<&'data [u8]>> {
+        let directory = match self.data_directory(IMAGE_DIRECTORY_ENTRY_RESOURCE) {
+            Some(directory) => directory,
+            None => return Ok(None),
+        };
+
+        let section = self
+            .section_for_rva(directory.virtual_address)
+            .ok_or("PeImage: resource directory is outside of any section")?;
+        let start = directory.virtual_address - section.virtual_address;
+        let len = section.size_of_raw_data.saturating_sub(start) as usize;
+        Ok(Some(self.slice_at_rva(directory.virtual_address, len)?))
+    }
+
+    /// Read the attributes of the resource directory of this image, if present.
+    pub fn resource_directory(&self) -> Result<Option<DirectoryMetadata>> {
+        self.resource_tree()?
+            .map(rsrc::read_directory_metadata)
+            .transpose()
+    }
+
+    /// Read all resources contained in the resource directory of this image.
+    pub fn resources(&self) -> Result<Vec<ResourceEntry>> {
+        self.resources_with(None)
+    }
+
+    /// Read all resources contained in the resource directory of this image,
+    /// borrowing their data from `mapping` if the image is parsed from its contents.
+    pub(crate) fn resources_with(&self, mapping: Option<&Mapping>) -> Result<Vec<ResourceEntry>> {
+        let tree = match self.resource_tree()? {
+            Some(tree) => tree,
+            None => return Ok(Vec::new()),
+        };
+
+        rsrc::read_directory_tree(tree, |_, entry| {
+            let data = self.slice_at_rva(entry.offset_to_data, entry.size as usize)?;
+            Ok(data::bytes(mapping, data))
+        })
+    }
+
+    /// Compute the image checksum using the algorithm of `CheckSumMappedFile`:
+    /// a 16-bit one's complement sum of the image (excluding the `CheckSum`
+    /// field) added to the image size.
+    pub fn compute_checksum(&self) -> u32 {
+        let field = self.optional_header_offset + CHECKSUM_OFFSET;
+        let byte = |offset: usize| match self.data.get(offset) {
+            Some(_) if (field..field + 4).contains(&offset) => 0,
+            Some(value) => *value as u32,
+            None => 0,
+        };
+
+        let mut sum = 0u32;
+        for offset in (0..self.data.len()).step_by(2) {
+            sum += byte(offset) | (byte(offset + 1) << 8);
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        sum = (sum & 0xffff) + (sum >> 16);
+        sum.wrapping_add(self.data.len() as u32)
+    }
+
+    /// Returns `true` if the `CheckSum` stored in the optional header is valid
+    pub fn is_checksum_valid(&self) -> bool {
+        self.checksum == self.compute_checksum()
+    }
+
+    /// Returns the certificate table (`IMAGE_DIRECTORY_ENTRY_SECURITY`) if present.
+    /// Unlike other data directories, its `virtual_address` is a file offset.
+    pub fn certificate_table(&self) -> Option<DataDirectory> {
+        self.data_directory(IMAGE_DIRECTORY_ENTRY_SECURITY)
+            .filter(|directory| directory.size != 0)
+    }
+
+    /// Returns `true` if the image carries an Authenticode signature
+    pub fn is_signed(&self) -> bool {
+        self.certificate_table().is_some()
+    }
+
+    /// Produce a copy of the image with the certificate table removed.
+    pub fn without_certificate_table(&self) -> Result<Vec<u8>> {
+        let directory = match self.certificate_table() {
+            Some(directory) => directory,
+            None => return Ok(self.data.to_vec()),
+        };
+
+        let start = directory.virtual_address as usize;
+        let end = start + directory.size as usize;
+        try_slice(self.data, start, directory.size as usize)?;
+        let mut image = self.data[..start].to_vec();
+        image.extend_from_slice(&self.data[end..]);
+
+        let offset = self.data_directories_offset
+            + IMAGE_DIRECTORY_ENTRY_SECURITY * IMAGE_DATA_DIRECTORY_SIZE;
+        image[offset..offset + IMAGE_DATA_DIRECTORY_SIZE].fill(0);
+        Ok(image)
+    }
+
+    /// Locate the index of the section holding the resource directory. Returns
+    /// `None` if the image has no resources or if the resource directory shares
+    /// its section with other data (as the section can not be rebuilt then).
+    fn resource_section_index(&self) -> Result<Option<usize>> {
+        let directory = match self.data_directory(IMAGE_DIRECTORY_ENTRY_RESOURCE) {
+            Some(directory) => directory,
+            None => return Ok(None),
+        };
+
+        match self
+            .sections
+            .iter()
+            .position(|section| section.contains_rva(directory.virtual_address))
+        {
+            Some(index) if self.sections[index].virtual_address == directory.virtual_address => {
+                Ok(Some(index))
+            }
+            Some(_) => Ok(None),
+            None => Err("PeImage: resource directory is outside of any section".into()),
+        }
+    }
+
+    /// Returns `true` if the section at `index` is the last section of the image
+    /// both in terms of virtual address and file position.
+    fn is_last_section(&self, index: usize) -> bool {
+        let section = &self.sections[index];
+        self.sections.iter().enumerate().all(|(i, other)| {
+            i == index
+                || (other.virtual_address < section.virtual_address
+                    && (other.size_of_raw_data == 0
+                        || other.pointer_to_raw_data < section.pointer_to_raw_data))
+        })
+    }
+
+    /// Virtual address of the section following the section at `index`
+    fn next_virtual_address(&self, index: usize) -> Option<u32> {
+        let section = &self.sections[index];
+        self.sections
+            .iter()
+            .map(|other| other.virtual_address)
+            .filter(|virtual_address| *virtual_address > section.virtual_address)
+            .min()
+    }
+
+    /// Returns `true` if `len` bytes fit into the raw data and the virtual
+    /// address range of the section at `index`.
+    fn fits_in_place(&self, index: usize, len: usize) -> bool {
+        let section = &self.sections[index];
+        let virtual_end = section.virtual_address as usize + len;
+        len <= section.size_of_raw_data as usize
+            && virtual_end <= self.next_virtual_address(index).unwrap_or(u32::MAX) as usize
+    }
+
+    /// Returns `true` if the section at `index` can be moved. This applies to sections
+    /// solely referenced by the base relocation directory (such as `.reloc`), which
+    /// is updated as the section moves; other sections may be referenced by code.
+    fn is_movable_section(&self, index: usize) -> bool {
+        let section = &self.sections[index];
+        let references = self
+            .data_directories
+            .iter()
+            .enumerate()
+            .filter(|(i, directory)| {
+                *i != IMAGE_DIRECTORY_ENTRY_SECURITY
+                    && directory.virtual_address != 0
+                    && section.contains_rva(directory.virtual_address)
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        references == [IMAGE_DIRECTORY_ENTRY_BASERELOC]
+    }
+
+    /// Returns `true` if all sections following the section at `index` are movable
+    /// and located past it both in terms of virtual address and file position.
+    fn can_shift_following(&self, index: usize) -> bool {
+        let section = &self.sections[index];
+        self.sections.iter().enumerate().all(|(i, other)| {
+            if i == index {
+                true
+            } else if other.virtual_address > section.virtual_address {
+                self.is_movable_section(i)
+                    && (other.size_of_raw_data == 0
+                        || other.pointer_to_raw_data as usize >= section.raw_end())
+            } else {
+                other.size_of_raw_data == 0
+                    || other.raw_end() <= section.pointer_to_raw_data as usize
+            }
+        })
+    }
+
+    /// Move the sections following the section at `index` so that it can hold
+    /// `len` bytes, updating data directories referring to the moved sections.
+    /// Returns the new raw data size of the section at `index`.
+    fn shift_following(&mut self, index: usize, len: usize) -> usize {
+        let section = &self.sections[index];
+        let raw_size =
+            align(len, self.file_alignment as usize).max(section.size_of_raw_data as usize);
+        let delta_raw = (raw_size - section.size_of_raw_data as usize) as u32;
+        let virtual_end = align(
+            section.virtual_address as usize + len,
+            self.section_alignment as usize,
+        ) as u32;
+        let delta_va =
+            virtual_end.saturating_sub(self.next_virtual_address(index).unwrap_or(virtual_end));
+        let virtual_address = section.virtual_address;
+
+        for (i, directory) in self.data_directories.iter_mut().enumerate() {
+            if i != IMAGE_DIRECTORY_ENTRY_SECURITY && directory.virtual_address > virtual_address {
+                directory.virtual_address += delta_va;
+            }
+        }
+        for other in self.sections.iter_mut() {
+            if other.virtual_address > virtual_address {
+                other.virtual_address += delta_va;
+                if other.size_of_raw_data != 0 {
+                    other.pointer_to_raw_data += delta_raw;
+                }
+            }
+        }
+        raw_size
+    }
+
+    /// File offset following the raw data of the last section, where the overlay starts.
+    pub fn overlay_offset(&self) -> usize {
+        self.sections
+            .iter()
+            .filter(|section| section.size_of_raw_data != 0)
+            .map(|section| section.raw_end())
+            .max()
+            .unwrap_or(self.size_of_headers as usize)
+            .min(self.data.len())
+    }
+
+    /// Locate the data following the raw data of the last section.
+    fn tail(&self) -> Result<Tail<'data>> {
+        let offset = self.overlay_offset();
+        match self.certificate_table() {
+            Some(directory) if directory.virtual_address as usize >= offset => {
+                let start = directory.virtual_address as usize;
+                let certificate = try_slice(self.data, start, directory.size as usize)?;
+                Ok(Tail {
+                    overlay: &self.data[offset..start],
+                    certificate: Some(certificate),
+                    trailing: &self.data[start + certificate.len()..],
+                })
+            }
+            _ => Ok(Tail {
+                overlay: &self.data[offset..],
+                certificate: None,
+                trailing: &[],
+            }),
+        }
+    }
+
+    /// Overlay data appended past the last section (such as an installer payload),
+    /// excluding the certificate table.
+    pub fn overlay(&self) -> Result<&'data [u8]> {
+        Ok(self.tail()?.overlay)
+    }
+
+    /// Produce a copy of the image with the overlay replaced by the supplied data.
+    /// The certificate table is moved past the new overlay.
+    pub fn with_overlay(&self, overlay: &[u8]) -> Result<Vec<u8>> {
+        let tail = self.tail()?;
+        let mut headers = self.clone();
+        let mut image = self.data[..self.overlay_offset()].to_vec();
+        headers.store_tail(&mut image, overlay, &tail);
+        let offset = self.data_directories_offset
+            + IMAGE_DIRECTORY_ENTRY_SECURITY * IMAGE_DATA_DIRECTORY_SIZE;
+        if let Some(directory) = headers.data_directories.get(IMAGE_DIRECTORY_ENTRY_SECURITY) {
+            store_u32le(&mut image[offset..], directory.virtual_address);
+            store_u32le(&mut image[offset + 4..], directory.size);
+        }
+        Ok(image)
+    }
+
+    /// Append the overlay followed by the certificate table of `tail` to the image
+    /// ending with the last section, updating the certificate table location.
+    fn store_tail(&mut self, image: &mut Vec<u8>, overlay: &[u8], tail: &Tail) {
+        image.extend_from_slice(overlay);
+        if let Some(certificate) = tail.certificate {
+            image.resize(align(image.len(), WIN_CERTIFICATE_ALIGNMENT), 0);
+            self.data_directories[IMAGE_DIRECTORY_ENTRY_SECURITY] = DataDirectory {
+                virtual_address: image.len() as u32,
+                size: certificate.len() as u32,
+            };
+            image.extend_from_slice(certificate);
+            image.extend_from_slice(tail.trailing);
+        }
+    }
+
+    /// Produce a new image where the resource section is rebuilt from the
+    /// supplied resources. If the resource section is not the last section and
+    /// the resources do not fit into it, the following sections are moved if
+    /// they are only referenced by the base relocation directory; otherwise (or if
+    /// the image has no resource section) a new `.rsrc` section is appended. The overlay
+    /// and the certificate table are retained and moved past the last section,
+    /// as are the attributes of the resource directory.
+    pub fn with_resources(&self, entries: &[ResourceEntry]) -> Result<Vec<u8>> {
+        let directory = self.resource_directory()?.unwrap_or_default();
+        self.rebuild(entries, &directory, None)
+    }
+
+    /// Rebuild the resource section using the supplied directory attributes,
+    /// optionally replacing the overlay.
+    pub(crate) fn rebuild(
+        &self,
+        entries: &[ResourceEntry],
+        directory: &DirectoryMetadata,
+        overlay: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        let tail = self.tail()?;
+        let overlay = overlay.unwrap_or(tail.overlay);
+        let layout = rsrc::build_directory_tree(entries, directory)?;
+        let len = layout.data_offset() + layout.data.len();
+        let mut headers = self.clone();
+        headers.ensure_data_directories(IMAGE_DIRECTORY_ENTRY_RESOURCE + 1)?;
+
+        // raw data of the sections following the resource section, which is
+        // stored past the rebuilt resource section
+        let overlay_offset = self.overlay_offset();
+        let mut following = overlay_offset..overlay_offset;
+        let (index, raw_size) = match self.resource_section_index()? {
+            Some(index) if self.is_last_section(index) => {
+                (index, align(len, self.file_alignment as usize))
+            }
+            Some(index) if self.fits_in_place(index, len) => {
+                following = self.sections[index].raw_end()..overlay_offset;
+                (index, self.sections[index].size_of_raw_data as usize)
+            }
+            Some(index) if self.can_shift_following(index) => {
+                following = self.sections[index].raw_end()..overlay_offset;
+                (index, headers.shift_following(index, len))
+            }
+            _ => {
+                headers.append_section(*b".rsrc\0\0\0")?;
+                (
+                    headers.sections.len() - 1,
+                    align(len, self.file_alignment as usize),
+                )
+            }
+        };
+
+        let section = &mut headers.sections[index];
+        let content = layout.to_section(section.virtual_address);
+        section.virtual_size = content.len() as u32;
+        section.size_of_raw_data = raw_size as u32;
+        section.characteristics |= IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ;
+        let pointer_to_raw_data = section.pointer_to_raw_data as usize;
+        headers.data_directories[IMAGE_DIRECTORY_ENTRY_RESOURCE] = DataDirectory {
+            virtual_address: section.virtual_address,
+            size: content.len() as u32,
+        };
+
+        let mut image = self.data[..pointer_to_raw_data.min(overlay_offset)].to_vec();
+        image.resize(pointer_to_raw_data, 0);
+        image.extend_from_slice(&content);
+        image.resize(pointer_to_raw_data + raw_size, 0);
+        image.extend_from_slice(&self.data[following]);
+        // the overlay and the certificate table follow the last section
+        // and have to be relocated as the resource section changes its size
+        headers.store_tail(&mut image, overlay, &tail);
+
+        headers.update_sizes();
+        headers.store_headers(&mut image)?;
+        Ok(image)
+    }
+
+    /// Returns `true` if the section table ending at `table_end` fits into the headers.
+    /// Space past the stored section table must be unused (zero), as linkers may
+    /// place data there (such as bound import descriptors).
+    fn headers_fit(&self, table_end: usize) -> bool {
+        let first_raw_data = self
+            .sections
+            .iter()
+            .filter(|section| section.size_of_raw_data != 0)
+            .map(|section| section.pointer_to_raw_data as usize)
+            .min()
+            .unwrap_or(usize::MAX);
+        let free = table_end <= self.headers_end
+            || self
+                .data
+                .get(self.headers_end..table_end)
+                .map(|slack| slack.iter().all(|byte| *byte == 0))
+                .unwrap_or(false);
+        table_end <= self.size_of_headers as usize && table_end <= first_raw_data && free
+    }
+
+    /// Extend the data directory table to hold at least `count` entries. The
+    /// optional header grows and the section table is moved accordingly.
+    fn ensure_data_directories(&mut self, count: usize) -> Result<()> {
+        if self.data_directories.len() >= count {
+            return Ok(());
+        }
+
+        let delta = (count - self.data_directories.len()) * IMAGE_DATA_DIRECTORY_SIZE;
+        let table_end =
+            self.section_table_offset + delta + self.sections.len() * IMAGE_SECTION_HEADER_SIZE;
+        if !self.headers_fit(table_end) {
+            return Err(format!(
+                "PeImage: no room to extend the data directory table to {count} entries"
+            )
+            .into());
+        }
+
+        self.data_directories
+            .resize(count, DataDirectory::default());
+        self.number_of_rva_and_sizes = count as u32;
+        self.section_table_offset += delta;
+        Ok(())
+    }
+
+    /// Append a new initialized data section header following the last section.
+    fn append_section(&mut self, name: [u8; 8]) -> Result<()> {
+        let table_end =
+            self.section_table_offset + (self.sections.len() + 1) * IMAGE_SECTION_HEADER_SIZE;
+        if !self.headers_fit(table_end) {
+            return Err("PeImage: no room for an additional section header".into());
+        }
+
+        let virtual_end = self
+            .sections
+            .iter()
+            .map(|section| section.virtual_end())
+            .max()
+            .unwrap_or(self.size_of_headers);
+        let raw_end = self
+            .sections
+            .iter()
+            .map(|section| section.raw_end())
+            .max()
+            .unwrap_or(self.size_of_headers as usize);
+
+        let virtual_address = align(virtual_end as usize, self.section_alignment as usize) as u32;
+        let pointer_to_raw_data = if self.section_alignment < PAGE_SIZE {
+            virtual_address
+        } else {
+            align(raw_end, self.file_alignment as usize) as u32
+        };
+        self.sections.push(Section {
+            name,
+            virtual_address,
+            pointer_to_raw_data,
+            characteristics: IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+            ..Default::default()
+        });
+
+        Ok(())
+    }
+
+    /// Recompute `SizeOfImage` from the section table.
+    fn update_sizes(&mut self) {
+        let virtual_end = self
+            .sections
+            .iter()
+            .map(|section| section.virtual_end())
+            .max()
+            .unwrap_or(self.size_of_headers);
+        self.size_of_image = align(virtual_end as usize, self.section_alignment as usize) as u32;
+    }
+
+    /// Store header fields tracked by this structure into the supplied image buffer.
+    fn store_headers(&self, image: &mut [u8]) -> Result<()> {
+        let table_end = self.section_table_offset + self.sections.len() * IMAGE_SECTION_HEADER_SIZE;
+        if table_end > image.len() {
+            return Err("PeImage: image is too small to hold the section table".into());
+        }
+
+        store_u16le(
+            &mut image[self.file_header_offset + 2..],
+            self.sections.len() as u16,
+        );
+        store_u16le(
+            &mut image[self.file_header_offset + 16..],
+            (self.section_table_offset - self.optional_header_offset) as u16,
+        );
+        let initialized_data: u32 = self
+            .sections
+            .iter()
+            .filter(|section| section.characteristics & IMAGE_SCN_CNT_INITIALIZED_DATA != 0)
+            .map(|section| section.size_of_raw_data)
+            .sum();
+        store_u32le(
+            &mut image[self.optional_header_offset + 8..],
+            initialized_data,
+        );
+        store_u32le(
+            &mut image[self.optional_header_offset + 56..],
+            self.size_of_image,
+        );
+        store_u32le(
+            &mut image[self.optional_header_offset + CHECKSUM_OFFSET..],
+            self.checksum,
+        );
+        store_u32le(
+            &mut image[self.data_directories_offset - 4..],
+            self.number_of_rva_and_sizes,
+        );
+        for (index, directory) in self.data_directories.iter().enumerate() {
+            let offset = self.data_directories_offset + index * IMAGE_DATA_DIRECTORY_SIZE;
+            store_u32le(&mut image[offset..], directory.virtual_address);
+            store_u32le(&mut image[offset + 4..], directory.size);
+        }
+        for (index, section) in self.sections.iter().enumerate() {
+            let offset = self.section_table_offset + index * IMAGE_SECTION_HEADER_SIZE;
+            section.store(&mut image[offset..offset + IMAGE_SECTION_HEADER_SIZE]);
+        }
+
+        Ok(())
+    }
+}
+
+/// Recompute and store the `CheckSum` of the supplied image, returning the new value.
+pub fn update_checksum(data: &mut [u8]) -> Result<u32> {
+    let image = PeImage::parse(data)?;
+    let checksum = image.compute_checksum();
+    let offset = image.optional_header_offset + CHECKSUM_OFFSET;
+    store_u32le(&mut data[offset..], checksum);
+    Ok(checksum)
+}
+
+/// Returns `true` if the image file carries a valid `CheckSum`.
+pub fn verify_checksum(file: &Path) -> Result<bool> {
+    Ok(PeImage::parse(&std::fs::read(file)?)?.is_checksum_valid())
+}
+
+/// Recompute the `CheckSum` of the image file, rewriting the file if the
+/// stored value is not valid. Returns `true` if the file has been modified.
+pub fn fix_checksum(file: &Path) -> Result<bool> {
+    let mut data = std::fs::read(file)?;
+    if PeImage::parse(&data)?.is_checksum_valid() {
+        return Ok(false);
+    }
+    update_checksum(&mut data)?;
+    // replace the file as it may be memory-mapped by loaded resources
+    write_file_atomic(file, &data)?;
+    Ok(true)
+}
+
+/// Pure-Rust resource backend for PE images, the counterpart of the Win32
+/// `BeginUpdateResource`, `UpdateResource` and `EndUpdateResource` functions.
+/// Updates are accumulated in memory and the resource section is rebuilt on commit.
+#[derive(Debug)]
+pub struct PeBackend {
+    storage: Storage,
+    /// image contents mapped when the update session was started
+    image: Option<Mapping>,
+    /// resources the image will contain once the update session is committed
+    entries: Vec<ResourceEntry>,
+    /// replacement of the overlay staged during the update session
+    overlay: Option<Vec<u8>>,
+    /// replacement of the resource directory attributes staged during the update session
+    directory: Option<DirectoryMetadata>,
+    signature_policy: SignaturePolicy,
+    update_checksum: bool,
+    deterministic: bool,
+}
+
+impl PeBackend {
+    /// Create a PE backend bound to the supplied file.
+    pub fn new(file: &Path) -> PeBackend {
+        PeBackend::with_storage(Storage::File(file.to_path_buf()))
+    }
+
+    /// Create a PE backend bound to the supplied storage.
+    pub fn with_storage(storage: Storage) -> PeBackend {
+        PeBackend {
+            storage,
+            image: None,
+            entries: Vec::new(),
+            overlay: None,
+            directory: None,
+            signature_policy: SignaturePolicy::default(),
+            update_checksum: true,
+            deterministic: false,
+        }
+    }
+
+    fn entries(&mut self) -> Result<&mut Vec<ResourceEntry>> {
+        if self.image.is_none() {
+            return Err(format!("resource file '{}' is not open", self.storage).into());
+        }
+        Ok(&mut self.entries)
+    }
+
+    fn position(&self, kind: &Id, name: &Id, lang: u16) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| &entry.kind == kind && &entry.name == name && entry.lang == lang)
+    }
+}
+
+impl ResourceBackend for PeBackend {
+    fn load(&self) -> Result<Vec<ResourceEntry>> {
+        let mapping = self.storage.map()?;
+        PeImage::parse(&mapping)?.resources_with(Some(&mapping))
+    }
+
+    fn begin(&mut self, delete_existing_resources: bool) -> Result<()> {
+        let mapping = self.storage.map()?;
+        let image = PeImage::parse(&mapping)?;
+        if image.is_signed() && self.signature_policy == SignaturePolicy::Refuse {
+            return Err(signed_image_error(&self.storage.to_string()));
+        }
+        self.entries = if delete_existing_resources {
+            Vec::new()
+        } else {
+            image.resources_with(Some(&mapping))?
+        };
+        self.image = Some(mapping);
+        Ok(())
+    }
+
+    fn update(&mut self, kind: &Id, name: &Id, lang: u16, data: &[u8]) -> Result<()> {
+        let position = self.position(kind, name, lang);
+        let entries = self.entries()?;
+        match position {
+            Some(index) => entries[index].data = data.into(),
+            None => entries.push(ResourceEntry::new(kind.clone(), name.clone(), lang, data)),
+        }
+        Ok(())
+    }
+
+    fn set_metadata(
+        &mut self,
+        kind: &Id,
+        name: &Id,
+        lang: u16,
+        metadata: &ResourceMetadata,
+    ) -> Result<()> {
+        let position = self.position(kind, name, lang);
+        let entries = self.entries()?;
+        match position {
+            Some(index) => {
+                entries[index].metadata = *metadata;
+                Ok(())
+            }
+            None => Err(format!("resource {kind:?} {name:?} lang {lang} does not exist").into()),
+        }
+    }
+
+    fn delete(&mut self, kind: &Id, name: &Id, lang: u16) -> Result<()> {
+        let position = self.position(kind, name, lang);
+        let entries = self.entries()?;
+        if let Some(index) = position {
+            entries.remove(index);
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        let storage = self.storage.clone();
+        self.commit_to(&storage)
+    }
+
+    fn commit_to(&mut self, storage: &Storage) -> Result<()> {
+        if let Some(mapping) = self.image.take() {
+            let overlay = self.overlay.take();
+            let image = PeImage::parse(&mapping)?;
+            let mut directory = match self.directory.take() {
+                Some(directory) => directory,
+                None => image.resource_directory()?.unwrap_or_default(),
+            };
+            if self.deterministic {
+                directory.time_date_stamp = 0;
+            }
+            let mut image = image.rebuild(&self.entries, &directory, overlay.as_deref())?;
+            // release the contents of the resource file before it is replaced
+            self.entries.clear();
+            drop(mapping);
+            if self.signature_policy == SignaturePolicy::Strip {
+                image = PeImage::parse(&image)?.without_certificate_table()?;
+            }
+            if self.update_checksum {
+                update_checksum(&mut image)?;
+            }
+            storage.write(image)?;
+        }
+        Ok(())
+    }
+
+    fn discard(&mut self) -> Result<()> {
+        self.image = None;
+        self.entries.clear();
+        self.overlay = None;
+        self.directory = None;
+        Ok(())
+    }
+
+    fn set_overlay(&mut self, overlay: &[u8]) -> Result<()> {
+        self.entries()?;
+        self.overlay = Some(overlay.to_vec());
+        Ok(())
+    }
+
+    fn load_directory(&self) -> Result<Option<DirectoryMetadata>> {
+        let data = self.storage.read()?;
+        PeImage::parse(&data)?.resource_directory()
+    }
+
+    fn set_directory(&mut self, directory: &DirectoryMetadata) -> Result<()> {
+        self.entries()?;
+        self.directory = Some(*directory);
+        Ok(())
+    }
+
+    fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    fn set_signature_policy(&mut self, policy: SignaturePolicy) {
+        self.signature_policy = policy;
+    }
+
+    fn set_update_checksum(&mut self, update_checksum: bool) {
+        self.update_checksum = update_checksum;
+    }
+}
+
+/// Error reported when opening a signed image with [`SignaturePolicy::Refuse`].
+pub(crate) fn signed_image_error(file: &str) -> Error {
+    format!(
+        "resource file '{}' is signed; modifying it invalidates the signature (see `SignaturePolicy`)",
+        file
+    )
+    .into()
+}
@@ -0,0 +1,473 @@
+//!
+//! Reader and writer for 32-bit Win32 resource (`.res`) files produced
+//! by resource compilers such as `rc.exe` or `llvm-rc`.
+//!
+
+use crate::backend::{ResourceBackend, Storage};
+use crate::data::{Mapping, ResourceBytes};
+use crate::error::Error;
+use crate::id::{folded, Id};
+use crate::resources::{ResourceEntry, ResourceMetadata};
+use crate::result::Result;
+use crate::utils::*;
+use manual_serializer::*;
+use std::path::Path;
+
+/// Byte size of the empty `RESOURCEHEADER` record found at the start of 32-bit `.res` files.
+pub const RES_SENTINEL_SIZE: usize = 32;
+
+/// Leading bytes of a 32-bit `.res` file (the empty sentinel record header:
+/// `DataSize` 0, `HeaderSize` 32, `TYPE` ordinal 0 and `NAME` ordinal 0).
+const RES_SIGNATURE: [u8; 16] = [
+    0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00,
+];
+
+/// Resource can be moved in memory (`MemoryFlags`)
+pub const MEMORY_FLAG_MOVEABLE: u16 = 0x0010;
+/// Resource data is read-only (`MemoryFlags`)
+pub const MEMORY_FLAG_PURE: u16 = 0x0020;
+/// Resource is loaded with the executable (`MemoryFlags`)
+pub const MEMORY_FLAG_PRELOAD: u16 = 0x0040;
+/// Resource can be discarded when no longer needed (`MemoryFlags`)
+pub const MEMORY_FLAG_DISCARDABLE: u16 = 0x1000;
+
+/// Memory flags assigned by resource compilers to resources of the supplied type.
+pub fn default_memory_flags(kind: &Id) -> u16 {
+    match kind {
+        // RT_CURSOR, RT_ICON
+        Id::Integer(1) | Id::Integer(3) => MEMORY_FLAG_MOVEABLE | MEMORY_FLAG_DISCARDABLE,
+        // RT_MENU, RT_DIALOG, RT_STRING, RT_FONTDIR, RT_FONT,
+        // RT_ACCELERATOR, RT_GROUP_CURSOR, RT_GROUP_ICON
+        Id::Integer(4..=9) | Id::Integer(12) | Id::Integer(14) => {
+            MEMORY_FLAG_MOVEABLE | MEMORY_FLAG_PURE | MEMORY_FLAG_DISCARDABLE
+        }
+        _ => MEMORY_FLAG_MOVEABLE | MEMORY_FLAG_PURE,
+    }
+}
+
+/// Returns `true` if the supplied data looks like a 32-bit `.res` file.
+pub fn is_res_file(data: &[u8]) -> bool {
+    data.len() >= RES_SENTINEL_SIZE && data[..RES_SIGNATURE.len()] == RES_SIGNATURE
+}
+
+/// Single record of a `.res` file (`RESOURCEHEADER` followed by the resource data).
+#[derive(Debug, Clone)]
+pub struct ResRecord {
+    /// resource type
+    pub kind: Id,
+    /// resource name
+    pub name: Id,
+    /// predefined resource data version (`DataVersion`)
+    pub data_version: u32,
+    /// resource attribute flags (`MemoryFlags`)
+    pub memory_flags: u16,
+    /// resource language (`LanguageId`)
+    pub lang: u16,
+    /// user-defined version (`Version`)
+    pub version: u32,
+    /// user-defined characteristics (`Characteristics`)
+    pub characteristics: u32,
+    /// raw resource data
+    pub data: ResourceBytes,
+}
+
+impl ResRecord {
+    /// Create a new record using default memory flags for the resource type.
+    pub fn new(kind: Id, name: Id, lang: u16, data: &[u8]) -> ResRecord {
+        ResRecord {
+            memory_flags: default_memory_flags(&kind),
+            kind,
+            name,
+            data_version: 0,
+            lang,
+            version: 0,
+            characteristics: 0,
+            data: data.into(),
+        }
+    }
+
+    /// Attributes stored in the record header.
+    pub fn metadata(&self) -> ResourceMetadata {
+        ResourceMetadata {
+            code_page: 0,
+            data_version: self.data_version,
+            memory_flags: Some(self.memory_flags),
+            version: self.version,
+            characteristics: self.characteristics,
+        }
+    }
+
+    /// Store the supplied attributes in the record header. The code page
+    /// is not recorded by `.res` files.
+    pub fn set_metadata(&mut self, metadata: &ResourceMetadata) {
+        self.data_version = metadata.data_version;
+        self.memory_flags = metadata
+            .memory_flags
+            .unwrap_or_else(|| default_memory_flags(&self.kind));
+        self.version = metadata.version;
+        self.characteristics = metadata.characteristics;
+    }
+
+    /// Returns `true` for the empty record marking a 32-bit `.res` file.
+    pub fn is_sentinel(&self) -> bool {
+        self.kind == Id::Integer(0) && self.name == Id::Integer(0) && self.data.is_empty()
+    }
+
+    /// Append the serialized record including trailing DWORD padding to `dest`.
+    pub fn store(&self, dest: &mut Vec<u8>) {
+        let mut header = Vec::new();
+        store_res_id(&mut header, &self.kind);
+        store_res_id(&mut header, &self.name);
+        // header fields following TYPE and NAME are DWORD aligned
+        // relative to the start of the record (DataSize and HeaderSize)
+        header.resize(align(header.len() + 8, 4) - 8, 0);
+        header.extend_from_slice(&self.data_version.to_le_bytes());
+        header.extend_from_slice(&self.memory_flags.to_le_bytes());
+        header.extend_from_slice(&self.lang.to_le_bytes());
+        header.extend_from_slice(&self.version.to_le_bytes());
+        header.extend_from_slice(&self.characteristics.to_le_bytes());
+
+        dest.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        dest.extend_from_slice(&(header.len() as u32 + 8).to_le_bytes());
+        dest.extend_from_slice(&header);
+        dest.extend_from_slice(&self.data);
+        dest.resize(align(dest.len(), 4), 0);
+    }
+}
+
+impl From<&ResourceEntry> for ResRecord {
+    fn from(entry: &ResourceEntry) -> ResRecord {
+        let mut record = ResRecord::new(
+            entry.kind.clone(),
+            entry.name.clone(),
+            entry.lang,
+            &entry.data,
+        );
+        record.set_metadata(&entry.metadata);
+        record
+    }
+}
+
+impl TryDeserialize for ResRecord {
+    type Error = Error;
+    fn try_deserialize(src: &mut Deserializer) -> Result<ResRecord> {
+        ResRecord::load(src, None)
+    }
+}
+
+impl ResRecord {
+    /// Load a record, borrowing its data from `mapping` if supplied, which
+    /// must hold the data the deserializer was created for.
+    fn load(src: &mut Deserializer, mapping: Option<&Mapping>) -> Result<ResRecord> {
+        let start = src.cursor();
+        let data_size = src.try_load_u32le()? as usize;
+        let header_size = src.try_load_u32le()? as usize;
+        if header_size < 16 || header_size > src.remaining() + 8 {
+            return Err(
+                format!("ResRecord: invalid header size {header_size} at offset {start}").into(),
+            );
+        }
+        if data_size > src.remaining() + 8 - header_size {
+            return Err(
+                format!("ResRecord: invalid data size {data_size} at offset {start}").into(),
+            );
+        }
+
+        let kind = load_res_id(src)?;
+        let name = load_res_id(src)?;
+        let padding = (4 - (src.cursor() - start) % 4) % 4;
+        src.try_offset(padding)?;
+        if src.cursor() + 16 > start + header_size {
+            return Err(format!("ResRecord: header at offset {start} is truncated").into());
+        }
+
+        let data_version = src.try_load_u32le()?;
+        let memory_flags = src.try_load_u16le()?;
+        let lang = src.try_load_u16le()?;
+        let version = src.try_load_u32le()?;
+        let characteristics = src.try_load_u32le()?;
+
+        src.try_set_cursor(start + header_size)?;
+        let data = match mapping {
+            Some(mapping) => {
+                let data = mapping.slice(src.cursor(), data_size)?;
+                src.try_offset(data_size)?;
+                data
+            }
+            None => src.try_load_u8_vec(data_size)?.into(),
+        };
+        let padding = (4 - (src.cursor() - start) % 4) % 4;
+        src.try_offset(padding.min(src.remaining()))?;
+
+        Ok(ResRecord {
+            kind,
+            name,
+            data_version,
+            memory_flags,
+            lang,
+            version,
+            characteristics,
+            data,
+        })
+    }
+}
+
+/// Load an ordinal (`0xffff` followed by the id) or a zero-terminated UTF-16 string.
+fn load_res_id(src: &mut Deserializer) -> Result<Id> {
+    ensure_remaining(src, 2)?;
+    let first = src.try_load_u16le()?;
+    if first == 0xffff {
+        ensure_remaining(src, 2)?;
+        return Ok(Id::Integer(src.try_load_u16le()?));
+    }
+
+    let mut text = vec![first];
+    let mut unit = first;
+    while unit != 0 {
+        ensure_remaining(src, 2)?;
+        unit = src.try_load_u16le()?;
+        text.push(unit);
+    }
+    text.pop();
+    // unpaired surrogates are replaced, as by the Win32 enumeration functions
+    Ok(Id::Text(String::from_utf16_lossy(&text)))
+}
+
+/// Store an ordinal (`0xffff` followed by the id) or a zero-terminated UTF-16 string.
+fn store_res_id(dest: &mut Vec<u8>, id: &Id) {
+    match id {
+        Id::Integer(id) => {
+            dest.extend_from_slice(&0xffffu16.to_le_bytes());
+            dest.extend_from_slice(&id.to_le_bytes());
+        }
+        Id::Text(text) => {
+            // names are stored uppercased, as by rc.exe and llvm-rc
+            for unit in folded(text).chain(std::iter::once(0)) {
+                dest.extend_from_slice(&unit.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// The deserializer panics when reading past the end of its buffer,
+/// so bounds are verified before reading untrusted data.
+fn ensure_remaining(src: &Deserializer, len: usize) -> Result<()> {
+    if src.remaining() < len {
+        return Err(format!(
+            "ResRecord: unexpected end of data at offset {}",
+            src.cursor()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Parsed 32-bit `.res` file.
+#[derive(Debug, Clone, Default)]
+pub struct ResFile {
+    /// resource records excluding the leading sentinel record
+    pub records: Vec<ResRecord>,
+}
+
+impl ResFile {
+    /// Parse the supplied `.res` file data.
+    pub fn parse(data: &[u8]) -> Result<ResFile> {
+        ResFile::parse_with(data, None)
+    }
+
+    /// Parse the contents of a `.res` file borrowing the resource data from them.
+    pub fn parse_mapping(mapping: &Mapping) -> Result<ResFile> {
+        ResFile::parse_with(mapping, Some(mapping))
+    }
+
+    fn parse_with(data: &[u8], mapping: Option<&Mapping>) -> Result<ResFile> {
+        if !is_res_file(data) {
+            return Err("ResFile::parse(): missing 32-bit resource file header".into());
+        }
+
+        let mut src = Deserializer::new(data);
+        let mut records = Vec::new();
+        while src.remaining() > 0 {
+            ensure_remaining(&src, 8)?;
+            let record = ResRecord::load(&mut src, mapping)?;
+            if !record.is_sentinel() {
+                records.push(record);
+            }
+        }
+
+        Ok(ResFile { records })
+    }
+
+    /// Obtain resources contained in this file.
+    pub fn resources(&self) -> Vec<ResourceEntry> {
+        self.records
+            .iter()
+            .map(|record| ResourceEntry {
+                kind: record.kind.clone(),
+                name: record.name.clone(),
+                lang: record.lang,
+                data: record.data.clone(),
+                metadata: record.metadata(),
+            })
+            .collect()
+    }
+}
+
+impl ResFile {
+    /// Serialize this file including the leading empty sentinel record.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        ResRecord {
+            kind: Id::Integer(0),
+            name: Id::Integer(0),
+            data_version: 0,
+            memory_flags: 0,
+            lang: 0,
+            version: 0,
+            characteristics: 0,
+            data: ResourceBytes::default(),
+        }
+        .store(&mut data);
+        for record in &self.records {
+            record.store(&mut data);
+        }
+        data
+    }
+
+    /// Add, replace or (if `data` is `None`) delete the resource identified by
+    /// `kind`, `name` and `lang`. Replacing the data of an existing record
+    /// retains its memory flags and characteristics.
+    pub fn update(&mut self, kind: &Id, name: &Id, lang: u16, data: Option<&[u8]>) {
+        let position = self.position(kind, name, lang);
+
+        match (position, data) {
+            (Some(index), Some(data)) => self.records[index].data = data.into(),
+            (Some(index), None) => {
+                self.records.remove(index);
+            }
+            (None, Some(data)) => {
+                self.records
+                    .push(ResRecord::new(kind.clone(), name.clone(), lang, data))
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Store the supplied attributes in the record of the resource identified by
+    /// `kind`, `name` and `lang`. Returns `false` if the resource does not exist.
+    pub fn set_metadata(
+        &mut self,
+        kind: &Id,
+        name: &Id,
+        lang: u16,
+        metadata: &ResourceMetadata,
+    ) -> bool {
+        match self.position(kind, name, lang) {
+            Some(index) => {
+                self.records[index].set_metadata(metadata);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn position(&self, kind: &Id, name: &Id, lang: u16) -> Option<usize> {
+        self.records
+            .iter()
+            .position(|record| &record.kind == kind && &record.name == name && record.lang == lang)
+    }
+}
+
+impl From<&[ResourceEntry]> for ResFile {
+    fn from(entries: &[ResourceEntry]) -> ResFile {
+        ResFile {
+            records: entries.iter().map(ResRecord::from).collect(),
+        }
+    }
+}
+
+/// Pure-Rust resource backend for 32-bit compiled resource (`.res`) files.
+/// Updates are accumulated in memory and the file is rewritten on commit.
+#[derive(Debug)]
+pub struct ResBackend {
+    storage: Storage,
+    /// resource file contents while an update session is in progress
+    res: Option<ResFile>,
+}
+
+impl ResBackend {
+    /// Create a `.res` backend bound to the supplied file.
+    pub fn new(file: &Path) -> ResBackend {
+        ResBackend::with_storage(Storage::File(file.to_path_buf()))
+    }
+
+    /// Create a `.res` backend bound to the supplied storage.
+    pub fn with_storage(storage: Storage) -> ResBackend {
+        ResBackend { storage, res: None }
+    }
+
+    fn res(&mut self) -> Result<&mut ResFile> {
+        match self.res.as_mut() {
+            Some(res) => Ok(res),
+            None => Err(format!("resource file '{}' is not open", self.storage).into()),
+        }
+    }
+}
+
+impl ResourceBackend for ResBackend {
+    fn load(&self) -> Result<Vec<ResourceEntry>> {
+        Ok(ResFile::parse_mapping(&self.storage.map()?)?.resources())
+    }
+
+    fn begin(&mut self, delete_existing_resources: bool) -> Result<()> {
+        let mut res = ResFile::parse_mapping(&self.storage.map()?)?;
+        if delete_existing_resources {
+            res.records.clear();
+        }
+        self.res = Some(res);
+        Ok(())
+    }
+
+    fn update(&mut self, kind: &Id, name: &Id, lang: u16, data: &[u8]) -> Result<()> {
+        self.res()?.update(kind, name, lang, Some(data));
+        Ok(())
+    }
+
+    fn delete(&mut self, kind: &Id, name: &Id, lang: u16) -> Result<()> {
+        self.res()?.update(kind, name, lang, None);
+        Ok(())
+    }
+
+    fn set_metadata(
+        &mut self,
+        kind: &Id,
+        name: &Id,
+        lang: u16,
+        metadata: &ResourceMetadata,
+    ) -> Result<()> {
+        if !self.res()?.set_metadata(kind, name, lang, metadata) {
+            return Err(format!("resource {kind:?} {name:?} lang {lang} does not exist").into());
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        let storage = self.storage.clone();
+        self.commit_to(&storage)
+    }
+
+    fn commit_to(&mut self, storage: &Storage) -> Result<()> {
+        if let Some(res) = self.res.take() {
+            let data = res.to_vec();
+            // release the contents of the resource file before it is replaced
+            drop(res);
+            storage.write(data)?;
+        }
+        Ok(())
+    }
+
+    fn discard(&mut self) -> Result<()> {
+        self.res = None;
+        Ok(())
+    }
+}
@@ -1,17 +1,25 @@
+use crate::backend::{BackendKind, ResourceBackend, Storage};
+use crate::coff::{self, Machine};
+use crate::data::ResourceBytes;
+use crate::error::Error;
+use crate::icon::IconGroup;
 use crate::id::*;
+use crate::lang::LangId;
+use crate::pe::{self, PeImage, SignaturePolicy};
+use crate::res::{ResFile, ResRecord};
 use crate::result::*;
-use crate::utils::*;
+use crate::rsrc::DirectoryMetadata;
+use crate::session::*;
+use crate::utils::{backup_file_path, write_file_atomic};
 use crate::version::*;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::mem::discriminant;
 use std::path::Path;
+use std::str::FromStr;
 use std::{
     fmt,
-    path::PathBuf,
-    sync::{Arc, Mutex},
-};
-use windows::{
-    core::PCSTR,
-    Win32::Foundation::{BOOL, HANDLE, HINSTANCE},
-    Win32::System::LibraryLoader::*,
+    sync::{Arc, Mutex, Weak},
 };
 
 pub mod resource_type {
@@ -28,18 +36,218 @@ pub mod resource_type {
     pub const CURSOR: Id = Id::Integer(1);
     pub const DIALOG: Id = Id::Integer(5);
     pub const DLGINCLUDE: Id = Id::Integer(17);
+    /// MFC dialog initialization data
+    pub const DLGINIT: Id = Id::Integer(240);
     pub const FONT: Id = Id::Integer(8);
     pub const FONTDIR: Id = Id::Integer(7);
+    pub const GROUP_CURSOR: Id = Id::Integer(12);
+    pub const GROUP_ICON: Id = Id::Integer(14);
     pub const HTML: Id = Id::Integer(23);
     pub const ICON: Id = Id::Integer(3);
     pub const MANIFEST: Id = Id::Integer(24);
     pub const MENU: Id = Id::Integer(4);
     pub const MESSAGETABLE: Id = Id::Integer(11);
     pub const PLUGPLAY: Id = Id::Integer(19);
+    pub const RCDATA: Id = Id::Integer(10);
+    pub const STRING: Id = Id::Integer(6);
+    /// MFC toolbar
+    pub const TOOLBAR: Id = Id::Integer(241);
     pub const VERSION: Id = Id::Integer(16);
     pub const VXD: Id = Id::Integer(20);
 }
 
+/// Windows resource type. Predefined types are represented by their own
+/// variants, other integer types by [`ResourceType::Integer`] and types
+/// identified by a string by [`ResourceType::Custom`].
+///
+/// Resource types are displayed using the name of their `RT_*` constant
+/// (`RT_GROUP_ICON`), `#<id>` for other integer types and the string for
+/// custom types. Parsing accepts the constant name with or without the `RT_`
+/// prefix (case-insensitive), `#<id>` and any other string as a custom type.
+///
+/// Resource types compare, hash and order like the [`Id`] they convert to:
+/// custom types case-insensitively.
+#[derive(Debug, Clone)]
+pub enum ResourceType {
+    Cursor,
+    Bitmap,
+    Icon,
+    Menu,
+    Dialog,
+    String,
+    FontDir,
+    Font,
+    Accelerator,
+    RcData,
+    MessageTable,
+    GroupCursor,
+    GroupIcon,
+    Version,
+    DlgInclude,
+    PlugPlay,
+    Vxd,
+    AniCursor,
+    AniIcon,
+    Html,
+    Manifest,
+    DlgInit,
+    Toolbar,
+    /// integer resource type without a predefined constant
+    Integer(u16),
+    /// resource type identified by a string
+    Custom(String),
+}
+
+/// Predefined resource types with their integer id and `RT_*` constant name
+const RESOURCE_TYPES: &[(ResourceType, u16, &str)] = &[
+    (ResourceType::Cursor, 1, "RT_CURSOR"),
+    (ResourceType::Bitmap, 2, "RT_BITMAP"),
+    (ResourceType::Icon, 3, "RT_ICON"),
+    (ResourceType::Menu, 4, "RT_MENU"),
+    (ResourceType::Dialog, 5, "RT_DIALOG"),
+    (ResourceType::String, 6, "RT_STRING"),
+    (ResourceType::FontDir, 7, "RT_FONTDIR"),
+    (ResourceType::Font, 8, "RT_FONT"),
+    (ResourceType::Accelerator, 9, "RT_ACCELERATOR"),
+    (ResourceType::RcData, 10, "RT_RCDATA"),
+    (ResourceType::MessageTable, 11, "RT_MESSAGETABLE"),
+    (ResourceType::GroupCursor, 12, "RT_GROUP_CURSOR"),
+    (ResourceType::GroupIcon, 14, "RT_GROUP_ICON"),
+    (ResourceType::Version, 16, "RT_VERSION"),
+    (ResourceType::DlgInclude, 17, "RT_DLGINCLUDE"),
+    (ResourceType::PlugPlay, 19, "RT_PLUGPLAY"),
+    (ResourceType::Vxd, 20, "RT_VXD"),
+    (ResourceType::AniCursor, 21, "RT_ANICURSOR"),
+    (ResourceType::AniIcon, 22, "RT_ANIICON"),
+    (ResourceType::Html, 23, "RT_HTML"),
+    (ResourceType::Manifest, 24, "RT_MANIFEST"),
+    (ResourceType::DlgInit, 240, "RT_DLGINIT"),
+    (ResourceType::Toolbar, 241, "RT_TOOLBAR"),
+];
+
+impl ResourceType {
+    /// Resource type identified by the supplied integer id.
+    pub fn from_integer(id: u16) -> ResourceType {
+        RESOURCE_TYPES
+            .iter()
+            .find(|(_, value, _)| *value == id)
+            .map(|(kind, _, _)| kind.clone())
+            .unwrap_or(ResourceType::Integer(id))
+    }
+
+    /// Integer id of the resource type (`None` for custom types).
+    pub fn to_integer(&self) -> Option<u16> {
+        match self {
+            ResourceType::Integer(id) => Some(*id),
+            ResourceType::Custom(_) => None,
+            kind => RESOURCE_TYPES
+                .iter()
+                .find(|(known, _, _)| discriminant(known) == discriminant(kind))
+                .map(|(_, id, _)| *id),
+        }
+    }
+
+    /// Name of the `RT_*` constant of predefined resource types.
+    pub fn constant_name(&self) -> Option<&'static str> {
+        let id = self.to_integer()?;
+        RESOURCE_TYPES
+            .iter()
+            .find(|(_, known, _)| *known == id)
+            .map(|(_, _, name)| *name)
+    }
+}
+
+impl From<&Id> for ResourceType {
+    fn from(id: &Id) -> ResourceType {
+        match id {
+            Id::Integer(id) => ResourceType::from_integer(*id),
+            Id::Text(text) => ResourceType::Custom(text.clone()),
+        }
+    }
+}
+
+impl From<Id> for ResourceType {
+    fn from(id: Id) -> ResourceType {
+        match id {
+            Id::Integer(id) => ResourceType::from_integer(id),
+            Id::Text(text) => ResourceType::Custom(text),
+        }
+    }
+}
+
+impl From<&ResourceType> for Id {
+    fn from(kind: &ResourceType) -> Id {
+        match kind {
+            ResourceType::Custom(text) => Id::Text(text.clone()),
+            kind => Id::Integer(kind.to_integer().unwrap_or_default()),
+        }
+    }
+}
+
+impl From<ResourceType> for Id {
+    fn from(kind: ResourceType) -> Id {
+        match kind {
+            ResourceType::Custom(text) => Id::Text(text),
+            kind => Id::from(&kind),
+        }
+    }
+}
+
+impl PartialEq for ResourceType {
+    fn eq(&self, other: &ResourceType) -> bool {
+        Id::from(self) == Id::from(other)
+    }
+}
+
+impl Eq for ResourceType {}
+
+impl Hash for ResourceType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Id::from(self).hash(state);
+    }
+}
+
+impl Ord for ResourceType {
+    fn cmp(&self, other: &ResourceType) -> Ordering {
+        Id::from(self).cmp(&Id::from(other))
+    }
+}
+
+impl PartialOrd for ResourceType {
+    fn partial_cmp(&self, other: &ResourceType) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for ResourceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceType::Integer(id) => write!(f, "#{id}"),
+            ResourceType::Custom(text) => write!(f, "{text}"),
+            kind => write!(f, "{}", kind.constant_name().unwrap_or_default()),
+        }
+    }
+}
+
+impl FromStr for ResourceType {
+    type Err = Error;
+    fn from_str(s: &str) -> std::result::Result<ResourceType, Error> {
+        if s.is_empty() {
+            return Err("ResourceType: empty resource type".into());
+        }
+        if s.starts_with('#') {
+            return Ok(ResourceType::from(Id::from_str(s)?));
+        }
+        let known = RESOURCE_TYPES
+            .iter()
+            .find(|(_, _, name)| name.eq_ignore_ascii_case(s) || name[3..].eq_ignore_ascii_case(s));
+        match known {
+            Some((kind, _, _)) => Ok(kind.clone()),
+            None => Ok(ResourceType::Custom(s.to_string())),
+        }
+    }
+}
+
 /// Placeholder for future data serialization (not implementated)
 #[derive(Debug, Clone)]
 pub struct ResourceDataInner {
@@ -69,6 +277,92 @@ pub enum ResourceData {
     Unknown(ResourceDataInner),
 }
 
+/// Attributes stored alongside the resource data. Executables and object files
+/// record the code page (`IMAGE_RESOURCE_DATA_ENTRY`), `.res` files record the
+/// remaining attributes (`RESOURCEHEADER`); attributes a file format does not
+/// store are ignored when the resource is written to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceMetadata {
+    /// code page of the resource data (`CodePage`)
+    pub code_page: u32,
+    /// predefined resource data version (`DataVersion`)
+    pub data_version: u32,
+    /// resource attribute flags (`MemoryFlags`); if `None`, the default
+    /// flags of the resource type are used (see [`res::default_memory_flags`](crate::res::default_memory_flags))
+    pub memory_flags: Option<u16>,
+    /// user-defined version (`Version`)
+    pub version: u32,
+    /// user-defined characteristics (`Characteristics`)
+    pub characteristics: u32,
+}
+
+/// Resource record decoded from a resource container (such as a PE image)
+/// before it is bound to a [`Resources`] resource manager.
+#[derive(Debug, Clone)]
+pub struct ResourceEntry {
+    /// resource type
+    pub kind: Id,
+    /// resource name
+    pub name: Id,
+    /// `u16` language associated with the resource (see [`LangId`])
+    pub lang: u16,
+    /// raw resource data, borrowed from the resource file it was loaded from
+    pub data: ResourceBytes,
+    /// attributes stored alongside the resource data
+    pub metadata: ResourceMetadata,
+}
+
+impl ResourceEntry {
+    /// Create a resource entry with default attributes.
+    pub fn new(kind: Id, name: Id, lang: u16, data: &[u8]) -> ResourceEntry {
+        ResourceEntry {
+            kind,
+            name,
+            lang,
+            data: data.into(),
+            metadata: ResourceMetadata::default(),
+        }
+    }
+
+    /// Language associated with the resource.
+    pub fn lang_id(&self) -> LangId {
+        LangId(self.lang)
+    }
+}
+
+/// Shared reference to the update session of an open resource file. The
+/// session is present only while the resource file is open for updates.
+pub type SharedSession = Arc<Mutex<Option<Session>>>;
+
+/// Shared list of resources held by the [`Resources`] resource manager.
+pub type SharedList = Arc<Mutex<Vec<Arc<Resource>>>>;
+
+/// Store `resource` in the resource list, replacing the data of an existing
+/// resource with the same type, name and language.
+fn list_update(list: &Mutex<Vec<Arc<Resource>>>, resource: &Resource) {
+    let mut list = list.lock().unwrap();
+    match list
+        .iter()
+        .find(|item| item.is(&resource.kind, &resource.name, resource.lang))
+    {
+        Some(item) if Arc::ptr_eq(&item.encoded, &resource.encoded) => {}
+        Some(item) => {
+            *item.encoded.lock().unwrap() = resource.encoded.lock().unwrap().clone();
+            if let Some(metadata) = *resource.metadata.lock().unwrap() {
+                item.metadata.lock().unwrap().replace(metadata);
+            }
+        }
+        None => list.push(Arc::new(resource.clone())),
+    }
+}
+
+/// Remove the resource identified by type, name and language from the resource list.
+fn list_remove(list: &Mutex<Vec<Arc<Resource>>>, kind: &Id, name: &Id, lang: u16) {
+    list.lock()
+        .unwrap()
+        .retain(|item| !item.is(kind, name, lang));
+}
+
 /// Structure representing a single resource
 #[derive(Clone)]
 pub struct Resource {
@@ -76,14 +370,20 @@ pub struct Resource {
     pub kind: Id,
     /// resource name
     pub name: Id,
-    /// `u16` language associated with the resource
+    /// `u16` language associated with the resource (see [`LangId`])
     pub lang: u16,
-    /// raw resource data
-    pub encoded: Arc<Mutex<Vec<u8>>>,
+    /// raw resource data; data loaded from a resource file refers to its
+    /// (memory-mapped) contents until it is replaced
+    pub encoded: Arc<Mutex<ResourceBytes>>,
     /// destructured resource data (not implemented)
     pub decoded: Arc<Mutex<Option<ResourceData>>>,
-    /// reference to the module handle that owns the resource
-    module_handle: Arc<Mutex<Option<HANDLE>>>,
+    /// attributes stored alongside the resource data; `None` unless loaded
+    /// from the resource file or explicitly set
+    metadata: Arc<Mutex<Option<ResourceMetadata>>>,
+    /// reference to the update session of the resource file that owns the resource
+    session: SharedSession,
+    /// reference to the resource list of the resource file that owns the resource
+    list: Weak<Mutex<Vec<Arc<Resource>>>>,
 }
 
 impl std::fmt::Debug for Resource {
@@ -101,48 +401,83 @@ impl std::fmt::Debug for Resource {
 
 impl Resource {
     /// Create a new resource instance bound to the [`Resources`] resource manager.
-    pub fn new(
+    pub fn new(resources: &Resources, rtype: Id, rname: Id, rlang: u16, data: &[u8]) -> Resource {
+        Resource::with_bytes(resources, rtype, rname, rlang, data.into())
+    }
+
+    fn with_bytes(
         resources: &Resources,
-        rtype: PCSTR,
-        rname: PCSTR,
+        rtype: Id,
+        rname: Id,
         rlang: u16,
-        data: &[u8],
+        data: ResourceBytes,
     ) -> Resource {
-        let typeid: Id = rtype.into();
         Resource {
-            kind: typeid,
-            name: rname.into(),
+            kind: rtype,
+            name: rname,
             lang: rlang,
-            encoded: Arc::new(Mutex::new(data.to_vec())),
+            encoded: Arc::new(Mutex::new(data)),
             decoded: Arc::new(Mutex::new(None)),
-            module_handle: resources.module_handle(),
+            metadata: Arc::new(Mutex::new(None)),
+            session: resources.session(),
+            list: Arc::downgrade(&resources.list),
+        }
+    }
+
+    fn from_entry(resources: &Resources, entry: &ResourceEntry) -> Resource {
+        let resource = Resource::with_bytes(
+            resources,
+            entry.kind.clone(),
+            entry.name.clone(),
+            entry.lang,
+            entry.data.clone(),
+        );
+        resource.metadata.lock().unwrap().replace(entry.metadata);
+        resource
+    }
+
+    fn entry(&self) -> ResourceEntry {
+        ResourceEntry {
+            kind: self.kind.clone(),
+            name: self.name.clone(),
+            lang: self.lang,
+            data: self.encoded.lock().unwrap().clone(),
+            metadata: self.metadata(),
         }
     }
 
+    /// Attributes stored alongside the resource data (code page, `.res` memory flags
+    /// and characteristics). Resources not loaded from a resource file have default attributes.
+    pub fn metadata(&self) -> ResourceMetadata {
+        self.metadata.lock().unwrap().unwrap_or_default()
+    }
+
+    /// Replace the attributes stored alongside the resource data. As with
+    /// [`Resource::replace()`], you must call [`Resource::update()`] following
+    /// this call to store the attributes in the actual module.
+    pub fn set_metadata(&self, metadata: ResourceMetadata) -> Result<&Self> {
+        self.metadata.lock().unwrap().replace(metadata);
+        Ok(self)
+    }
+
+    /// Language associated with the resource.
+    pub fn lang_id(&self) -> LangId {
+        LangId(self.lang)
+    }
+
+    fn is(&self, kind: &Id, name: &Id, lang: u16) -> bool {
+        &self.kind == kind && &self.name == name && self.lang == lang
+    }
+
     /// Remove resource from the associated module (deletes the resource)
     pub fn remove(&self) -> Result<&Self> {
-        if let Some(handle) = self.module_handle.lock().unwrap().as_ref() {
-            let success = unsafe {
-                UpdateResourceA(
-                    *handle,
-                    self.kind.clone(),
-                    self.name.clone(),
-                    self.lang,
-                    None,
-                    0,
-                )
-                .as_bool()
-            };
-
-            if !success {
-                return Err(format!(
-                    "Resources::load(): Error removing resources: {:?}",
-                    get_last_error()
-                )
-                .into());
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.delete(&self.kind, &self.name, self.lang);
+            if let Some(list) = self.list.upgrade() {
+                list_remove(&list, &self.kind, &self.name, self.lang);
             }
         } else {
-            return Err("Resource::replace(): resource file is not open".into());
+            return Err("Resource::remove(): resource file is not open".into());
         };
 
         Ok(self)
@@ -152,35 +487,25 @@ impl Resource {
     /// the data in the resource structure. You must call [`Resource::update()`]
     /// following this call to update the resoruce data in the actual module.
     pub fn replace(&self, data: &[u8]) -> Result<&Self> {
-        *self.encoded.lock().unwrap() = data.to_vec();
+        *self.encoded.lock().unwrap() = data.into();
         Ok(self)
     }
 
     /// Store this resource in the resource module (creates new or updates)
     pub fn update(&self) -> Result<&Self> {
-        if let Some(handle) = self.module_handle.lock().unwrap().as_ref() {
-            let encoded = self.encoded.lock().unwrap();
-            let success = unsafe {
-                UpdateResourceA(
-                    *handle,
-                    self.kind.clone(),
-                    self.name.clone(),
-                    self.lang,
-                    Some(std::mem::transmute(encoded.as_ptr())),
-                    encoded.len() as u32,
-                )
-                .as_bool()
-            };
-
-            if !success {
-                return Err(format!(
-                    "Resources::load(): Error removing resources: {:?}",
-                    get_last_error()
-                )
-                .into());
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.update(
+                &self.kind,
+                &self.name,
+                self.lang,
+                &self.encoded.lock().unwrap(),
+                *self.metadata.lock().unwrap(),
+            );
+            if let Some(list) = self.list.upgrade() {
+                list_update(&list, self);
             }
         } else {
-            return Err("Resource::replace(): resource file is not open".into());
+            return Err("Resource::update(): resource file is not open".into());
         };
 
         Ok(self)
@@ -188,68 +513,152 @@ impl Resource {
 }
 
 /// Data structure representing a resource file. This data structure
-/// points to a `.res` or `.exe` file and allows loading and modifying
-/// resource in this file.
+/// points to a `.res` or `.exe` file (or holds its contents in memory)
+/// and allows loading and modifying resource in this file.
 #[derive(Debug)]
 pub struct Resources {
-    file: PathBuf,
-    module_handle: Arc<Mutex<Option<HANDLE>>>,
+    storage: Storage,
+    /// explicitly selected backend kind (detected from the file if `None`)
+    backend_kind: Option<BackendKind>,
+    session: SharedSession,
+    signature_policy: SignaturePolicy,
+    update_checksum: bool,
+    deterministic: bool,
+    backup: bool,
     /// resources contained in the supplied file represented by the [`Resource`] data structure.
-    pub list: Arc<Mutex<Vec<Arc<Resource>>>>,
+    /// While the resource file is open, the list reflects the staged changes.
+    pub list: SharedList,
 }
 
 impl Resources {
     /// Create new instance of the resource manager bound to a specific resource file.
     /// Once created, the resource file should be opened using [`Resources::open()`] or [`Resources::load()`].
+    /// The backend used to access the file is selected by [`BackendKind::detect`].
     pub fn new(file: &Path) -> Resources {
+        Resources::with_storage(Storage::File(file.to_path_buf()))
+    }
+
+    /// Create new instance of the resource manager holding the contents of a
+    /// resource file in memory. Changes are applied to the in-memory copy when
+    /// the resource file is closed and can be retrieved using [`Resources::to_bytes()`].
+    pub fn from_bytes(data: &[u8]) -> Resources {
+        Resources::with_storage(Storage::from_bytes(data))
+    }
+
+    fn with_storage(storage: Storage) -> Resources {
         Resources {
-            file: file.to_path_buf(),
-            module_handle: Arc::new(Mutex::new(None)),
+            storage,
+            backend_kind: None,
+            session: Arc::new(Mutex::new(None)),
+            signature_policy: SignaturePolicy::default(),
+            update_checksum: true,
+            deterministic: false,
+            backup: false,
             list: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    /// Load resources from the resource file.  This function does not need to be called
-    /// explicitly as [`Resources::open`] will call it. It is useful if you want to load
-    /// resources for extraction purposes only.
-    pub fn load(&self) -> Result<()> {
-        unsafe {
-            let handle = LoadLibraryExA(
-                pcstr!(self.file.to_str().unwrap()),
-                None,
-                // LOAD_LIBRARY_FLAGS::default()
-                DONT_RESOLVE_DLL_REFERENCES | LOAD_LIBRARY_AS_DATAFILE,
-            )?;
-
-            let ptr: *const Resources = std::mem::transmute(&*self);
-            let success =
-                EnumResourceTypesA(handle, Some(enum_types), std::mem::transmute(ptr)).as_bool();
-
-            FreeLibrary(handle);
-
-            if !success {
-                return Err(format!(
-                    "Resources::load(): Error enumerating resources: {:?}",
-                    get_last_error()
-                )
-                .into());
-            }
+    /// Create new instance of the resource manager bound to a specific resource file
+    /// using an explicitly selected backend.
+    pub fn with_backend(file: &Path, backend_kind: BackendKind) -> Resources {
+        let mut resources = Resources::new(file);
+        resources.backend_kind = Some(backend_kind);
+        resources
+    }
+
+    /// Returns the kind of the backend used to access the resource file.
+    pub fn backend_kind(&self) -> Result<BackendKind> {
+        match self.backend_kind {
+            Some(backend_kind) => Ok(backend_kind),
+            None => BackendKind::detect_storage(&self.storage),
         }
+    }
+
+    fn create_backend(&self) -> Result<Box<dyn ResourceBackend>> {
+        let mut backend = self
+            .backend_kind()?
+            .create_with_storage(self.storage.clone())?;
+        backend.set_signature_policy(self.signature_policy);
+        backend.set_update_checksum(self.update_checksum);
+        backend.set_deterministic(self.deterministic);
+        Ok(backend)
+    }
+
+    /// Returns `true` if the resource file is an executable carrying
+    /// an Authenticode signature.
+    pub fn is_signed(&self) -> Result<bool> {
+        let data = self.storage.read()?;
+        if !pe::is_pe_image(&data) {
+            return Ok(false);
+        }
+        Ok(PeImage::parse(&data)?.is_signed())
+    }
+
+    /// Returns the policy applied when modifying signed executables.
+    pub fn signature_policy(&self) -> SignaturePolicy {
+        self.signature_policy
+    }
+
+    /// Select how signed executables are handled. By default, opening a signed
+    /// executable fails ([`SignaturePolicy::Refuse`]); this must be set before
+    /// [`Resources::open`] is called.
+    pub fn set_signature_policy(&mut self, policy: SignaturePolicy) {
+        self.signature_policy = policy;
+    }
+
+    /// Select whether the `CheckSum` of executables is recomputed when the
+    /// changes are committed (enabled by default). Must be set before
+    /// [`Resources::open`] is called.
+    pub fn set_update_checksum(&mut self, update_checksum: bool) {
+        self.update_checksum = update_checksum;
+    }
 
+    /// Select whether the time stamp of the resource directory is zeroed when
+    /// the changes are committed, producing reproducible output (disabled by
+    /// default). Must be set before [`Resources::open`] is called.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Select whether a copy of the original resource file (`<file>.bak`) is
+    /// kept when the changes are applied by [`Resources::close`] (disabled by default).
+    pub fn set_backup(&mut self, backup: bool) {
+        self.backup = backup;
+    }
+
+    /// Load resources from the resource file replacing the contents of [`Resources::list`].
+    /// This function does not need to be called explicitly as [`Resources::open`] will call it.
+    /// It is useful if you want to load resources for extraction purposes only.
+    pub fn load(&self) -> Result<()> {
+        self.load_with(self.create_backend()?.as_ref())?;
         Ok(())
     }
 
-    pub fn module_handle(&self) -> Arc<Mutex<Option<HANDLE>>> {
-        self.module_handle.clone()
+    fn load_with(&self, backend: &dyn ResourceBackend) -> Result<Vec<ResourceEntry>> {
+        let entries = backend.load()?;
+        self.reset_list(&entries);
+        Ok(entries)
+    }
+
+    fn reset_list(&self, entries: &[ResourceEntry]) {
+        let list = entries
+            .iter()
+            .map(|entry| Arc::new(Resource::from_entry(self, entry)))
+            .collect();
+        *self.list.lock().unwrap() = list;
+    }
+
+    pub fn session(&self) -> SharedSession {
+        self.session.clone()
     }
 
     /// returns `true` if the resource file is currently open
     pub fn is_open(&self) -> bool {
-        self.module_handle.lock().unwrap().is_some()
+        self.session.lock().unwrap().is_some()
     }
 
-    /// Open the resource file. This function opens a Windows handle to
-    /// the resource file and must be followed by [`Resources::close`].
+    /// Open the resource file. This function starts an update session of the
+    /// resource file backend and must be followed by [`Resources::close`].
     pub fn open(&mut self) -> Result<&Self> {
         self.open_impl(false)
     }
@@ -262,22 +671,14 @@ impl Resources {
 
     fn open_impl(&mut self, delete_existing_resources: bool) -> Result<&Self> {
         if self.is_open() {
-            return Err(
-                format!("resource '{}' is already open", self.file.to_str().unwrap()).into(),
-            );
+            return Err(format!("resource '{}' is already open", self.storage).into());
         }
 
-        self.load()?;
-
-        let handle = unsafe {
-            BeginUpdateResourceA(
-                pcstr!(self.file.to_str().unwrap()),
-                delete_existing_resources,
-            )?
-        };
-
-        self.module_handle.lock().unwrap().replace(handle);
-        // self.handle.lock().unwrap().replace(handle);
+        let backend = self.create_backend()?;
+        let entries = self.load_with(backend.as_ref())?;
+        let session = Session::begin(backend, &entries, delete_existing_resources)?;
+        self.reset_list(session.original());
+        self.session.lock().unwrap().replace(session);
 
         Ok(self)
     }
@@ -294,19 +695,11 @@ impl Resources {
     /// [`Resource`] struct as it ensures that all supplied information is correct.
     /// This method is provided for advanced usage only.
     pub fn remove_with_args(&self, kind: &Id, name: &Id, lang: u16) -> Result<&Self> {
-        // if let Some(handle) = self.handle.lock().unwrap().as_ref() {
-        if let Some(handle) = self.module_handle.lock().unwrap().as_ref() {
-            let success = unsafe { UpdateResourceA(*handle, kind, name, lang, None, 0).as_bool() };
-
-            if !success {
-                return Err(format!(
-                    "Resources::load(): Error removing resources: {:?}",
-                    get_last_error()
-                )
-                .into());
-            }
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.delete(kind, name, lang);
+            list_remove(&self.list, kind, name, lang);
         } else {
-            return Err(format!("resource '{}' is not open", self.file.to_str().unwrap()).into());
+            return Err(format!("resource '{}' is not open", self.storage).into());
         };
 
         Ok(self)
@@ -328,60 +721,217 @@ impl Resources {
     /// as well as a `u8` slice containing the raw resource data.  Please note that if this function fails
     /// the entire resoruce update set may fail.
     pub fn replace_with_args(&self, kind: &Id, name: &Id, lang: u16, data: &[u8]) -> Result<&Self> {
-        if let Some(handle) = self.module_handle.lock().unwrap().as_ref() {
-            let success = unsafe {
-                UpdateResourceA(
-                    *handle,
-                    kind,
-                    name,
-                    lang,
-                    Some(std::mem::transmute(data.as_ptr())),
-                    data.len() as u32,
-                )
-                .as_bool()
-            };
-
-            if !success {
-                return Err(format!(
-                    "Resources::load(): Error updating resources: {:?}",
-                    get_last_error()
-                )
-                .into());
-            }
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.update(kind, name, lang, data, None);
+            list_update(
+                &self.list,
+                &Resource::new(self, kind.clone(), name.clone(), lang, data),
+            );
+        } else {
+            return Err(format!("resource file '{}' is not open", self.storage).into());
+        };
+
+        Ok(self)
+    }
+
+    /// Returns the overlay of an executable: data appended past its last section
+    /// (such as the payload of an installer or a self-extracting archive).
+    /// The overlay is retained when resources are modified.
+    pub fn overlay(&self) -> Result<Vec<u8>> {
+        let data = self.storage.read()?;
+        if !pe::is_pe_image(&data) {
+            return Ok(Vec::new());
+        }
+        Ok(PeImage::parse(&data)?.overlay()?.to_vec())
+    }
+
+    /// Replace the overlay of an executable with the supplied data once the
+    /// resource file is closed. The replacement is staged like resource changes
+    /// (see [`Resources::pending_overlay()`]); resource file types without an
+    /// overlay report an error on close or [`Resources::dry_run()`].
+    pub fn set_overlay(&self, data: &[u8]) -> Result<&Self> {
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.set_overlay(data);
+        } else {
+            return Err(format!("resource file '{}' is not open", self.storage).into());
+        };
+
+        Ok(self)
+    }
+
+    /// Returns the attributes of the resource directory (time stamp, version and
+    /// characteristics) of executables and object files, or `None` if the resource
+    /// file has no resource directory.
+    pub fn directory(&self) -> Result<Option<DirectoryMetadata>> {
+        self.create_backend()?.load_directory()
+    }
+
+    /// Replace the attributes of the resource directory once the resource file is closed.
+    /// The replacement is staged like resource changes (see [`Resources::pending_directory()`]);
+    /// resource file types without a resource directory report an error on close or
+    /// [`Resources::dry_run()`].
+    pub fn set_directory(&self, directory: &DirectoryMetadata) -> Result<&Self> {
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.set_directory(directory);
         } else {
-            return Err(format!(
-                "resource file '{}' is not open",
-                self.file.to_str().unwrap()
-            )
-            .into());
+            return Err(format!("resource file '{}' is not open", self.storage).into());
         };
 
         Ok(self)
     }
 
+    /// Returns the contents of the resource file. For resource files held in
+    /// memory (see [`Resources::from_bytes()`]), this is the modified image once
+    /// the changes are applied by [`Resources::close()`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.storage.read()
+    }
+
     /// Close the resource file.  This applies all the changes (updates) to the resource file.
-    pub fn close(&mut self) {
-        if let Some(handle) = self.module_handle.lock().unwrap().take() {
-            unsafe {
-                EndUpdateResourceA(handle, false);
-            };
+    /// The changes are written to a temporary file which then replaces the resource file,
+    /// leaving the resource file intact if the update fails. If the backup copy
+    /// (see [`Resources::set_backup()`]) can not be created, the resource file
+    /// remains open with all changes staged.
+    pub fn close(&mut self) -> Result<()> {
+        if self.is_open() {
+            self.store_backup()?;
         }
+        let session = self.session.lock().unwrap().take();
+        if let Some(session) = session {
+            self.detach_list();
+            session.commit(None)?;
+        }
+        Ok(())
     }
 
-    /// Close the resource file discarding all changes.
+    /// Copy the resource data borrowed from the resource file, which
+    /// can not be replaced on Windows while it is memory-mapped.
+    /// Elsewhere replacing the file leaves the mapped contents intact.
+    fn detach_list(&self) {
+        if cfg!(windows) {
+            for item in self.list.lock().unwrap().iter() {
+                item.encoded.lock().unwrap().to_mut();
+            }
+        }
+    }
+
+    fn store_backup(&self) -> Result<()> {
+        if let (true, Storage::File(file)) = (self.backup, &self.storage) {
+            std::fs::copy(file, backup_file_path(file))?;
+        }
+        Ok(())
+    }
+
+    /// Close the resource file writing the changes to the supplied file instead
+    /// of the resource file, which is left unchanged. The resource list is then
+    /// reloaded from the resource file, as by [`Resources::discard()`].
+    pub fn save_as(&mut self, file: &Path) -> Result<()> {
+        let session = self.session.lock().unwrap().take();
+        match session {
+            Some(session) => {
+                self.detach_list();
+                session.commit(Some(&Storage::File(file.to_path_buf())))?;
+                self.load()
+            }
+            None => Err(format!("resource file '{}' is not open", self.storage).into()),
+        }
+    }
+
+    /// Close the resource file discarding all changes. The resource list
+    /// is restored to the resources contained in the resource file.
     pub fn discard(&mut self) {
-        if let Some(handle) = self.module_handle.lock().unwrap().take() {
-            unsafe {
-                EndUpdateResourceA(handle, true);
-            };
+        let session = self.session.lock().unwrap().take();
+        if let Some(session) = session {
+            let _ = session.discard();
+            let _ = self.load();
+        }
+    }
+
+    /// Returns resource changes staged since the resource file was opened. If it was
+    /// opened by [`Resources::open_delete_existing_resources()`], the removal of the
+    /// resources it contained is reported first; these removals can not be discarded.
+    /// Overlay and resource directory replacements are reported by
+    /// [`Resources::pending_overlay()`] and [`Resources::pending_directory()`].
+    pub fn pending_changes(&self) -> Vec<PendingChange> {
+        match self.session.lock().unwrap().as_ref() {
+            Some(session) => session.pending_changes(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the replacement of the overlay staged since the resource file was opened.
+    pub fn pending_overlay(&self) -> Option<Vec<u8>> {
+        let session = self.session.lock().unwrap();
+        session.as_ref()?.overlay().map(|overlay| overlay.to_vec())
+    }
+
+    /// Returns the replacement of the resource directory attributes staged
+    /// since the resource file was opened.
+    pub fn pending_directory(&self) -> Option<DirectoryMetadata> {
+        let session = self.session.lock().unwrap();
+        session.as_ref()?.directory().copied()
+    }
+
+    /// Discard the change staged for the resource identified by type, name and lang.
+    /// Returns the discarded change, if any.
+    pub fn clear_pending_change(&self, kind: &Id, name: &Id, lang: u16) -> Option<PendingChange> {
+        let mut session = self.session.lock().unwrap();
+        let session = session.as_mut()?;
+        let change = session.clear(kind, name, lang)?;
+        match session.find_original(kind, name, lang) {
+            Some(entry) => list_update(&self.list, &Resource::from_entry(self, entry)),
+            None => list_remove(&self.list, kind, name, lang),
         }
+        Some(change)
+    }
+
+    /// Discard all changes staged since the resource file was opened, including
+    /// overlay and resource directory replacements, keeping it open.
+    pub fn clear_pending_changes(&self) {
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.clear_all();
+            self.reset_list(session.original());
+        }
+    }
+
+    /// Verify that the staged changes, including overlay and resource directory
+    /// replacements, can be applied without modifying the resource file, returning
+    /// the resource changes that [`Resources::close`] would apply. The resource
+    /// file remains open.
+    pub fn dry_run(&self) -> Result<Vec<PendingChange>> {
+        let session = self.session.lock().unwrap();
+        let session = match session.as_ref() {
+            Some(session) => session,
+            None => {
+                return Err(format!("resource file '{}' is not open", self.storage).into());
+            }
+        };
+        let mut backend = self.create_backend()?;
+        backend.begin(session.delete_existing_resources())?;
+        if let Err(err) = session.apply(backend.as_mut()) {
+            let _ = backend.discard();
+            return Err(err);
+        }
+        backend.commit_to(&Storage::from_bytes(&[]))?;
+        Ok(session.pending_changes())
     }
 
     /// Create a new resource entry in the resource file. This function
     /// expects a valid [`Resource`] structure containing an appropriate
-    /// resource type, name and raw data.
+    /// resource type, name and raw data. If the resource file is open, the
+    /// resource is stored on close; an existing resource with the same type,
+    /// name and language is replaced.
     pub fn insert(&self, r: Resource) {
-        self.list.lock().unwrap().push(Arc::new(r))
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.update(
+                &r.kind,
+                &r.name,
+                r.lang,
+                &r.encoded.lock().unwrap(),
+                *r.metadata.lock().unwrap(),
+            );
+        }
+        list_update(&self.list, &r);
     }
 
     /// Locate a resource entry by type and name.
@@ -395,6 +945,65 @@ impl Resources {
         None
     }
 
+    /// Save resources currently held in [`Resources::list`] as a
+    /// 32-bit compiled resource (`.res`) file at the supplied path.
+    pub fn save_res(&self, file: &Path) -> Result<()> {
+        let res = ResFile {
+            records: self
+                .list
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|resource| ResRecord::from(&resource.entry()))
+                .collect(),
+        };
+        // the file may be the one the resource data is borrowed from
+        self.detach_list();
+        write_file_atomic(file, &res.to_vec())
+    }
+
+    /// Store resources contained in the resource list in a COFF object file
+    /// (`.obj`) for the given machine, as produced by `cvtres.exe`.
+    pub fn save_coff(&self, file: &Path, machine: Machine) -> Result<()> {
+        let entries = self
+            .list
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|resource| resource.entry())
+            .collect::<Vec<_>>();
+        let object = coff::write_object(&entries, machine)?;
+        drop(entries);
+        self.detach_list();
+        write_file_atomic(file, &object)
+    }
+
+    /// Build an `.ico` file from the icon group (`RT_GROUP_ICON`) resource with
+    /// the supplied name and the icon (`RT_ICON`) resources it refers to.
+    pub fn get_icon_group(&self, name: &Id) -> Result<Option<Vec<u8>>> {
+        let list = self.list.lock().unwrap();
+        let group = match list
+            .iter()
+            .find(|item| item.kind == resource_type::GROUP_ICON && &item.name == name)
+        {
+            Some(group) => group,
+            None => return Ok(None),
+        };
+        let icons = |id: u16| {
+            let icons = list
+                .iter()
+                .filter(|item| item.kind == resource_type::ICON && item.name == Id::Integer(id));
+            let mut icons = icons.collect::<Vec<_>>();
+            // prefer the language of the group
+            icons.sort_by_key(|item| item.lang != group.lang);
+            icons
+                .first()
+                .map(|item| item.encoded.lock().unwrap().to_vec())
+        };
+        let ico = IconGroup::parse(&group.encoded.lock().unwrap())?.to_ico(icons)?;
+        Ok(Some(ico))
+    }
+
     /// Locate and deserialize VS_VERSIONINFO structure (represented by [`VersionInfo`]).
     pub fn get_version_info(&self) -> Result<Option<VersionInfo>> {
         for item in self.list.lock().unwrap().iter() {
@@ -409,42 +1018,6 @@ impl Resources {
 
 impl Drop for Resources {
     fn drop(&mut self) {
-        self.close();
+        let _ = self.close();
     }
 }
-
-unsafe extern "system" fn enum_languages(
-    hmodule: HINSTANCE,
-    lptype: PCSTR,
-    lpname: PCSTR,
-    lang: u16,
-    lparam: isize,
-) -> BOOL {
-    let rptr: *const Resources = std::mem::transmute(lparam);
-    let hresinfo = match FindResourceExA(hmodule, lptype, lpname, lang) {
-        Ok(hresinfo) => hresinfo,
-        Err(e) => panic!("Unable to find resource {hmodule:?} {lptype:?} {lpname:?} {lang}: {e}"),
-    };
-    let resource = LoadResource(hmodule, hresinfo);
-    let len = SizeofResource(hmodule, hresinfo);
-    let data_ptr = LockResource(resource);
-    let data = std::slice::from_raw_parts(std::mem::transmute(data_ptr), len as usize);
-    let resources = &*rptr;
-    resources.insert(Resource::new(resources, lptype, lpname, lang, data));
-    BOOL(1)
-}
-
-unsafe extern "system" fn enum_names(
-    hmodule: HINSTANCE,
-    lptype: PCSTR,
-    lpname: PCSTR,
-    lparam: isize,
-) -> BOOL {
-    EnumResourceLanguagesA(hmodule, lptype, lpname, Some(enum_languages), lparam);
-    BOOL(1)
-}
-
-unsafe extern "system" fn enum_types(hmodule: HINSTANCE, lptype: PCSTR, lparam: isize) -> BOOL {
-    EnumResourceNamesA(hmodule, lptype, Some(enum_names), lparam);
-    BOOL(1)
-}
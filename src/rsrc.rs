@@ -0,0 +1,402 @@
+//!
+//! Reader and writer for the `IMAGE_RESOURCE_DIRECTORY` tree stored
+//! in the resource section (`.rsrc`) of PE images.
+//!
+
+use crate::data::ResourceBytes;
+use crate::error::Error;
+use crate::id::{folded, Id};
+use crate::resources::{ResourceEntry, ResourceMetadata};
+use crate::result::Result;
+use crate::utils::*;
+use manual_serializer::*;
+
+/// Byte size of the `IMAGE_RESOURCE_DIRECTORY` header
+pub const IMAGE_RESOURCE_DIRECTORY_SIZE: usize = 16;
+/// Byte size of the `IMAGE_RESOURCE_DIRECTORY_ENTRY` record
+pub const IMAGE_RESOURCE_DIRECTORY_ENTRY_SIZE: usize = 8;
+/// Byte size of the `IMAGE_RESOURCE_DATA_ENTRY` record
+pub const IMAGE_RESOURCE_DATA_ENTRY_SIZE: usize = 16;
+/// High bit flag marking a named entry or a subdirectory offset
+pub const IMAGE_RESOURCE_FLAG: u32 = 0x8000_0000;
+
+/// Directory levels of the resource tree: type, name and language.
+const DIRECTORY_DEPTH: usize = 3;
+
+/// `IMAGE_RESOURCE_DIRECTORY` header
+#[derive(Debug, Clone, Default)]
+pub struct Directory {
+    pub characteristics: u32,
+    pub time_date_stamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub number_of_named_entries: u16,
+    pub number_of_id_entries: u16,
+}
+
+impl TryDeserialize for Directory {
+    type Error = Error;
+    fn try_deserialize(src: &mut Deserializer) -> Result<Directory> {
+        Ok(Directory {
+            characteristics: src.try_load_u32le()?,
+            time_date_stamp: src.try_load_u32le()?,
+            major_version: src.try_load_u16le()?,
+            minor_version: src.try_load_u16le()?,
+            number_of_named_entries: src.try_load_u16le()?,
+            number_of_id_entries: src.try_load_u16le()?,
+        })
+    }
+}
+
+/// Attributes of the resource directory (`IMAGE_RESOURCE_DIRECTORY` header
+/// fields other than the entry counts). Resource compilers and linkers store
+/// the same values in all directory tables of the tree; they are read from
+/// the root directory and applied to all tables when the tree is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirectoryMetadata {
+    pub characteristics: u32,
+    pub time_date_stamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+}
+
+impl From<&Directory> for DirectoryMetadata {
+    fn from(directory: &Directory) -> DirectoryMetadata {
+        DirectoryMetadata {
+            characteristics: directory.characteristics,
+            time_date_stamp: directory.time_date_stamp,
+            major_version: directory.major_version,
+            minor_version: directory.minor_version,
+        }
+    }
+}
+
+/// `IMAGE_RESOURCE_DATA_ENTRY` record
+#[derive(Debug, Clone, Default)]
+pub struct DataEntry {
+    pub offset_to_data: u32,
+    pub size: u32,
+    pub code_page: u32,
+    pub reserved: u32,
+}
+
+impl TryDeserialize for DataEntry {
+    type Error = Error;
+    fn try_deserialize(src: &mut Deserializer) -> Result<DataEntry> {
+        Ok(DataEntry {
+            offset_to_data: src.try_load_u32le()?,
+            size: src.try_load_u32le()?,
+            code_page: src.try_load_u32le()?,
+            reserved: src.try_load_u32le()?,
+        })
+    }
+}
+
+/// Walks the resource directory tree located in the `tree` buffer (which must
+/// start at the root directory as all offsets within the tree are relative to it).
+/// The `resolve` closure receives the offset of each `IMAGE_RESOURCE_DATA_ENTRY`
+/// within the tree and the entry itself and must return the resource data.
+/// This is needed as data entries refer to the data by RVA in images while
+/// object files express them via relocations.
+pub(crate) fn read_directory_tree<F>(tree: &[u8], resolve: F) -> Result<Vec<ResourceEntry>>
+where
+    F: Fn(usize, &DataEntry) -> Result<ResourceBytes>,
+{
+    let mut entries = Vec::new();
+    let mut path = Vec::with_capacity(DIRECTORY_DEPTH);
+    walk(tree, 0, &mut path, &resolve, &mut entries)?;
+    Ok(entries)
+}
+
+fn walk<F>(
+    tree: &[u8],
+    offset: usize,
+    path: &mut Vec<u32>,
+    resolve: &F,
+    entries: &mut Vec<ResourceEntry>,
+) -> Result<()>
+where
+    F: Fn(usize, &DataEntry) -> Result<ResourceBytes>,
+{
+    let directory: Directory =
+        Deserializer::new(try_slice(tree, offset, IMAGE_RESOURCE_DIRECTORY_SIZE)?).try_load()?;
+    let count =
+        directory.number_of_named_entries as usize + directory.number_of_id_entries as usize;
+    let table = try_slice(
+        tree,
+        offset + IMAGE_RESOURCE_DIRECTORY_SIZE,
+        count * IMAGE_RESOURCE_DIRECTORY_ENTRY_SIZE,
+    )?;
+
+    let mut src = Deserializer::new(table);
+    for _ in 0..count {
+        let name = src.try_load_u32le()?;
+        let offset_to_data = src.try_load_u32le()?;
+        let is_directory = offset_to_data & IMAGE_RESOURCE_FLAG != 0;
+        let target = (offset_to_data & !IMAGE_RESOURCE_FLAG) as usize;
+
+        path.push(name);
+        match (path.len() < DIRECTORY_DEPTH, is_directory) {
+            (true, true) => walk(tree, target, path, resolve, entries)?,
+            (false, false) => {
+                let data_entry: DataEntry =
+                    Deserializer::new(try_slice(tree, target, IMAGE_RESOURCE_DATA_ENTRY_SIZE)?)
+                        .try_load()?;
+                entries.push(ResourceEntry {
+                    kind: read_id(tree, path[0])?,
+                    name: read_id(tree, path[1])?,
+                    lang: path[2] as u16,
+                    data: resolve(target, &data_entry)?,
+                    metadata: ResourceMetadata {
+                        code_page: data_entry.code_page,
+                        ..Default::default()
+                    },
+                });
+            }
+            _ => {
+                return Err(format!(
+                    "malformed resource directory: unexpected {} at level {}",
+                    if is_directory {
+                        "subdirectory"
+                    } else {
+                        "data entry"
+                    },
+                    path.len()
+                )
+                .into())
+            }
+        }
+        path.pop();
+    }
+
+    Ok(())
+}
+
+/// Read the attributes of the root directory of the tree located in the `tree` buffer.
+pub(crate) fn read_directory_metadata(tree: &[u8]) -> Result<DirectoryMetadata> {
+    let directory: Directory =
+        Deserializer::new(try_slice(tree, 0, IMAGE_RESOURCE_DIRECTORY_SIZE)?).try_load()?;
+    Ok(DirectoryMetadata::from(&directory))
+}
+
+/// Decode the name field of a directory entry, which is either an
+/// integer id or an offset to a length-prefixed UTF-16 string.
+fn read_id(tree: &[u8], name: u32) -> Result<Id> {
+    if name & IMAGE_RESOURCE_FLAG == 0 {
+        return Ok(Id::Integer(name as u16));
+    }
+
+    let offset = (name & !IMAGE_RESOURCE_FLAG) as usize;
+    let len = Deserializer::new(try_slice(tree, offset, 2)?).try_load_u16le()? as usize;
+    let mut src = Deserializer::new(try_slice(tree, offset + 2, len * 2)?);
+    let text = src.try_load_u16le_vec(len)?;
+    // unpaired surrogates are replaced, as by the Win32 enumeration functions
+    Ok(Id::Text(String::from_utf16_lossy(&text)))
+}
+
+/// Serialized resource directory tree produced by [`build_directory_tree`].
+/// The directory tables, data entries and strings are kept separately from
+/// the resource data, mirroring the `.rsrc$01` / `.rsrc$02` split used by
+/// the Microsoft toolchain.
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryLayout {
+    /// directory tables, data entries and name strings
+    pub tree: Vec<u8>,
+    /// resource data, each item aligned on an 8 byte boundary
+    pub data: Vec<u8>,
+    /// offsets of `IMAGE_RESOURCE_DATA_ENTRY` records within `tree`; the
+    /// `OffsetToData` field of each record holds the offset within `data`.
+    pub data_entries: Vec<usize>,
+}
+
+impl DirectoryLayout {
+    /// Byte offset of the resource data when the tree and the data
+    /// are stored sequentially within a single section.
+    pub fn data_offset(&self) -> usize {
+        align(self.tree.len(), 8)
+    }
+
+    /// Produce resource section contents to be located at the supplied `rva`.
+    pub fn to_section(&self, rva: u32) -> Vec<u8> {
+        let data_offset = self.data_offset();
+        let mut section = self.tree.clone();
+        for offset in &self.data_entries {
+            let field = &mut section[*offset..*offset + 4];
+            let value = load_u32le(field) + rva + data_offset as u32;
+            store_u32le(field, value);
+        }
+        section.resize(data_offset, 0);
+        section.extend_from_slice(&self.data);
+        section
+    }
+}
+
+/// Resource names of a single resource type paired with their language entries
+type NameDirectories<'entries> = Vec<(Id, Vec<&'entries ResourceEntry>)>;
+
+/// Serialize the supplied resources into a sorted `IMAGE_RESOURCE_DIRECTORY` tree
+/// whose directory tables carry the supplied attributes. The layout places all
+/// directory tables first (breadth-first), followed by data entries, name strings
+/// and finally the resource data.
+pub(crate) fn build_directory_tree(
+    entries: &[ResourceEntry],
+    directory: &DirectoryMetadata,
+) -> Result<DirectoryLayout> {
+    let mut sorted: Vec<&ResourceEntry> = entries.iter().collect();
+    // `Id` ordering matches the directory order: names (case-insensitive) before ids
+    sorted.sort_by(|a, b| {
+        a.kind
+            .cmp(&b.kind)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.lang.cmp(&b.lang))
+    });
+
+    // group entries into type and name directories
+    let mut types: Vec<(Id, NameDirectories)> = Vec::new();
+    for entry in sorted {
+        if types
+            .last()
+            .map(|(kind, _)| kind != &entry.kind)
+            .unwrap_or(true)
+        {
+            types.push((entry.kind.clone(), Vec::new()));
+        }
+        let names = &mut types.last_mut().unwrap().1;
+        match names.last_mut() {
+            Some((name, langs)) if name == &entry.name => {
+                if langs
+                    .last()
+                    .map(|last| last.lang == entry.lang)
+                    .unwrap_or(false)
+                {
+                    return Err(format!(
+                        "duplicate resource {:?} {:?} lang {}",
+                        entry.kind, entry.name, entry.lang
+                    )
+                    .into());
+                }
+                langs.push(entry);
+            }
+            _ => names.push((entry.name.clone(), vec![entry])),
+        }
+    }
+
+    // compute the byte size of directory tables, data entries and strings
+    let table_len =
+        |count: usize| IMAGE_RESOURCE_DIRECTORY_SIZE + count * IMAGE_RESOURCE_DIRECTORY_ENTRY_SIZE;
+    let mut tables_len = table_len(types.len());
+    let mut entry_count = 0;
+    for (_, names) in &types {
+        tables_len += table_len(names.len());
+        for (_, langs) in names {
+            tables_len += table_len(langs.len());
+            entry_count += langs.len();
+        }
+    }
+    let data_entries_offset = tables_len;
+    let strings_offset = data_entries_offset + entry_count * IMAGE_RESOURCE_DATA_ENTRY_SIZE;
+
+    let mut tree = vec![0u8; strings_offset];
+    let mut strings: Vec<u8> = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    let mut data_entries = Vec::with_capacity(entry_count);
+
+    let mut name_field = |id: &Id| -> Result<u32> {
+        match id {
+            Id::Integer(id) => Ok(*id as u32),
+            Id::Text(text) => {
+                let offset = strings_offset + strings.len();
+                // names are stored uppercased, as by rc.exe and UpdateResource,
+                // so that FindResource matches them in the sorted directory
+                let units: Vec<u16> = folded(text).collect();
+                if units.len() > u16::MAX as usize {
+                    return Err(format!("resource name '{text}' is too long").into());
+                }
+                strings.extend_from_slice(&(units.len() as u16).to_le_bytes());
+                for unit in units {
+                    strings.extend_from_slice(&unit.to_le_bytes());
+                }
+                Ok(IMAGE_RESOURCE_FLAG | offset as u32)
+            }
+        }
+    };
+
+    // directory table offsets in breadth-first order
+    let mut cursor = table_len(types.len());
+    let mut type_tables = Vec::with_capacity(types.len());
+    for (_, names) in &types {
+        type_tables.push(cursor);
+        cursor += table_len(names.len());
+    }
+    let mut name_tables = Vec::with_capacity(types.len());
+    for (_, names) in &types {
+        let mut tables = Vec::with_capacity(names.len());
+        for (_, langs) in names {
+            tables.push(cursor);
+            cursor += table_len(langs.len());
+        }
+        name_tables.push(tables);
+    }
+
+    let mut root = Vec::with_capacity(types.len());
+    for (t, (kind, names)) in types.iter().enumerate() {
+        root.push((
+            name_field(kind)?,
+            IMAGE_RESOURCE_FLAG | type_tables[t] as u32,
+        ));
+        let mut type_entries = Vec::with_capacity(names.len());
+        for (n, (name, langs)) in names.iter().enumerate() {
+            let table = name_tables[t][n];
+            type_entries.push((name_field(name)?, IMAGE_RESOURCE_FLAG | table as u32));
+            let mut lang_entries = Vec::with_capacity(langs.len());
+            for entry in langs {
+                let offset =
+                    data_entries_offset + data_entries.len() * IMAGE_RESOURCE_DATA_ENTRY_SIZE;
+                store_u32le(&mut tree[offset..], data.len() as u32);
+                store_u32le(&mut tree[offset + 4..], entry.data.len() as u32);
+                store_u32le(&mut tree[offset + 8..], entry.metadata.code_page);
+                data.extend_from_slice(&entry.data);
+                data.resize(align(data.len(), 8), 0);
+                data_entries.push(offset);
+                lang_entries.push((entry.lang as u32, offset as u32));
+            }
+            store_directory_table(&mut tree, table, directory, &lang_entries);
+        }
+        store_directory_table(&mut tree, type_tables[t], directory, &type_entries);
+    }
+    store_directory_table(&mut tree, 0, directory, &root);
+
+    tree.extend_from_slice(&strings);
+    tree.resize(align(tree.len(), 4), 0);
+
+    Ok(DirectoryLayout {
+        tree,
+        data,
+        data_entries,
+    })
+}
+
+/// Store an `IMAGE_RESOURCE_DIRECTORY` header followed by its entries at `offset`.
+fn store_directory_table(
+    tree: &mut [u8],
+    offset: usize,
+    directory: &DirectoryMetadata,
+    entries: &[(u32, u32)],
+) {
+    store_u32le(&mut tree[offset..], directory.characteristics);
+    store_u32le(&mut tree[offset + 4..], directory.time_date_stamp);
+    store_u16le(&mut tree[offset + 8..], directory.major_version);
+    store_u16le(&mut tree[offset + 10..], directory.minor_version);
+    let named = entries
+        .iter()
+        .filter(|(name, _)| name & IMAGE_RESOURCE_FLAG != 0)
+        .count();
+    store_u16le(&mut tree[offset + 12..], named as u16);
+    store_u16le(&mut tree[offset + 14..], (entries.len() - named) as u16);
+    for (index, (name, offset_to_data)) in entries.iter().enumerate() {
+        let entry =
+            offset + IMAGE_RESOURCE_DIRECTORY_SIZE + index * IMAGE_RESOURCE_DIRECTORY_ENTRY_SIZE;
+        store_u32le(&mut tree[entry..], *name);
+        store_u32le(&mut tree[entry + 4..], *offset_to_data);
+    }
+}
@@ -0,0 +1,317 @@
+//!
+//! Update session of an open resource file, tracking changes staged
+//! between [`Resources::open`](crate::Resources::open) and
+//! [`Resources::close`](crate::Resources::close).
+//!
+
+use crate::backend::{ResourceBackend, Storage};
+use crate::id::Id;
+use crate::resources::{ResourceEntry, ResourceMetadata};
+use crate::result::Result;
+use crate::rsrc::DirectoryMetadata;
+use std::fmt;
+
+/// Operation performed by a [`PendingChange`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeAction {
+    /// the resource does not exist in the resource file and is created
+    Insert,
+    /// the data of an existing resource is replaced
+    Replace,
+    /// an existing resource is removed
+    Remove,
+}
+
+/// Change of a single resource staged during an update session.
+#[derive(Clone)]
+pub struct PendingChange {
+    /// operation performed on the resource
+    pub action: ChangeAction,
+    /// resource type
+    pub kind: Id,
+    /// resource name
+    pub name: Id,
+    /// `u16` language associated with the resource
+    pub lang: u16,
+    /// size of the resource data in the resource file (`None` for inserts)
+    pub old_size: Option<usize>,
+    /// size of the resource data once the change is applied (`None` for removals)
+    pub new_size: Option<usize>,
+    /// attributes stored with the resource data; if `None`, the attributes of
+    /// a replaced resource are retained
+    pub metadata: Option<ResourceMetadata>,
+    data: Option<Vec<u8>>,
+}
+
+impl fmt::Debug for PendingChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingChange")
+            .field("action", &self.action)
+            .field("kind", &self.kind)
+            .field("name", &self.name)
+            .field("lang", &self.lang)
+            .field("old_size", &self.old_size)
+            .field("new_size", &self.new_size)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+impl PendingChange {
+    /// Resource data stored by the change (`None` for removals)
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
+    fn is(&self, kind: &Id, name: &Id, lang: u16) -> bool {
+        &self.kind == kind && &self.name == name && self.lang == lang
+    }
+}
+
+/// Update session of a resource file: the resource backend the session was
+/// started with and the changes applied to it once the session is committed.
+#[derive(Debug)]
+pub struct Session {
+    backend: Box<dyn ResourceBackend>,
+    delete_existing_resources: bool,
+    /// resources present in the resource file when the session was started
+    original: Vec<ResourceEntry>,
+    /// removal of the resources present in the resource file if existing resources are deleted
+    removed: Vec<PendingChange>,
+    changes: Vec<PendingChange>,
+    /// replacement of the overlay of an executable
+    overlay: Option<Vec<u8>>,
+    /// replacement of the resource directory attributes
+    directory: Option<DirectoryMetadata>,
+}
+
+impl Session {
+    /// Start an update session of the supplied backend. `entries` are the
+    /// resources currently contained in the resource file.
+    pub(crate) fn begin(
+        mut backend: Box<dyn ResourceBackend>,
+        entries: &[ResourceEntry],
+        delete_existing_resources: bool,
+    ) -> Result<Session> {
+        backend.begin(delete_existing_resources)?;
+        let (original, removed) = if delete_existing_resources {
+            let removed = entries
+                .iter()
+                .map(|entry| PendingChange {
+                    action: ChangeAction::Remove,
+                    kind: entry.kind.clone(),
+                    name: entry.name.clone(),
+                    lang: entry.lang,
+                    old_size: Some(entry.data.len()),
+                    new_size: None,
+                    metadata: None,
+                    data: None,
+                })
+                .collect();
+            (Vec::new(), removed)
+        } else {
+            (entries.to_vec(), Vec::new())
+        };
+        Ok(Session {
+            backend,
+            delete_existing_resources,
+            original,
+            removed,
+            changes: Vec::new(),
+            overlay: None,
+            directory: None,
+        })
+    }
+
+    /// Returns `true` if the resources present in the resource file are removed on commit.
+    pub fn delete_existing_resources(&self) -> bool {
+        self.delete_existing_resources
+    }
+
+    /// Changes staged during this session in the order they were made.
+    pub fn changes(&self) -> &[PendingChange] {
+        &self.changes
+    }
+
+    /// Changes applied on commit: the removal of the resources present in the
+    /// resource file if existing resources are deleted, followed by the staged changes.
+    pub fn pending_changes(&self) -> Vec<PendingChange> {
+        self.removed.iter().chain(&self.changes).cloned().collect()
+    }
+
+    /// Resources the resource file contains unless changed during this session.
+    pub fn original(&self) -> &[ResourceEntry] {
+        &self.original
+    }
+
+    /// Locate the resource the resource file contains unless changed during this session.
+    pub fn find_original(&self, kind: &Id, name: &Id, lang: u16) -> Option<&ResourceEntry> {
+        self.original
+            .iter()
+            .find(|entry| &entry.kind == kind && &entry.name == name && entry.lang == lang)
+    }
+
+    fn original_size(&self, kind: &Id, name: &Id, lang: u16) -> Option<usize> {
+        self.find_original(kind, name, lang)
+            .map(|entry| entry.data.len())
+    }
+
+    fn stage(&mut self, change: PendingChange) {
+        match self
+            .changes
+            .iter()
+            .position(|pending| pending.is(&change.kind, &change.name, change.lang))
+        {
+            Some(index) => self.changes[index] = change,
+            None => self.changes.push(change),
+        }
+    }
+
+    /// Stage creation or replacement of a resource, optionally replacing its attributes.
+    pub fn update(
+        &mut self,
+        kind: &Id,
+        name: &Id,
+        lang: u16,
+        data: &[u8],
+        metadata: Option<ResourceMetadata>,
+    ) {
+        let old_size = self.original_size(kind, name, lang);
+        self.stage(PendingChange {
+            action: match old_size {
+                Some(_) => ChangeAction::Replace,
+                None => ChangeAction::Insert,
+            },
+            kind: kind.clone(),
+            name: name.clone(),
+            lang,
+            old_size,
+            new_size: Some(data.len()),
+            metadata,
+            data: Some(data.to_vec()),
+        });
+    }
+
+    /// Stage removal of a resource. Removing a resource inserted during
+    /// this session discards the insert.
+    pub fn delete(&mut self, kind: &Id, name: &Id, lang: u16) {
+        match self.original_size(kind, name, lang) {
+            Some(old_size) => self.stage(PendingChange {
+                action: ChangeAction::Remove,
+                kind: kind.clone(),
+                name: name.clone(),
+                lang,
+                old_size: Some(old_size),
+                new_size: None,
+                metadata: None,
+                data: None,
+            }),
+            None => {
+                self.clear(kind, name, lang);
+            }
+        }
+    }
+
+    /// Discard the change staged for the supplied resource, returning it.
+    pub fn clear(&mut self, kind: &Id, name: &Id, lang: u16) -> Option<PendingChange> {
+        let index = self
+            .changes
+            .iter()
+            .position(|pending| pending.is(kind, name, lang))?;
+        Some(self.changes.remove(index))
+    }
+
+    /// Replacement of the overlay staged during this session.
+    pub fn overlay(&self) -> Option<&[u8]> {
+        self.overlay.as_deref()
+    }
+
+    /// Stage replacement of the overlay of an executable.
+    pub fn set_overlay(&mut self, data: &[u8]) {
+        self.overlay = Some(data.to_vec());
+    }
+
+    /// Replacement of the resource directory attributes staged during this session.
+    pub fn directory(&self) -> Option<&DirectoryMetadata> {
+        self.directory.as_ref()
+    }
+
+    /// Stage replacement of the resource directory attributes.
+    pub fn set_directory(&mut self, directory: &DirectoryMetadata) {
+        self.directory = Some(*directory);
+    }
+
+    /// Discard all staged changes, including overlay and resource directory replacements.
+    pub fn clear_all(&mut self) {
+        self.changes.clear();
+        self.overlay = None;
+        self.directory = None;
+    }
+
+    /// Apply the staged changes to the supplied backend.
+    pub(crate) fn apply(&self, backend: &mut dyn ResourceBackend) -> Result<()> {
+        apply_changes(
+            &self.changes,
+            self.overlay.as_deref(),
+            self.directory.as_ref(),
+            backend,
+        )
+    }
+
+    /// Apply the staged changes and commit them to the resource file, or to
+    /// `storage` if supplied. The session is discarded if the changes can not be applied.
+    pub(crate) fn commit(self, storage: Option<&Storage>) -> Result<()> {
+        let Session {
+            mut backend,
+            original,
+            changes,
+            overlay,
+            directory,
+            ..
+        } = self;
+        // release resource data borrowed from the resource file before it is replaced
+        drop(original);
+        if let Err(err) = apply_changes(
+            &changes,
+            overlay.as_deref(),
+            directory.as_ref(),
+            backend.as_mut(),
+        ) {
+            let _ = backend.discard();
+            return Err(err);
+        }
+        match storage {
+            Some(storage) => backend.commit_to(storage),
+            None => backend.commit(),
+        }
+    }
+
+    /// End the session discarding all changes.
+    pub(crate) fn discard(mut self) -> Result<()> {
+        self.backend.discard()
+    }
+}
+
+fn apply_changes(
+    changes: &[PendingChange],
+    overlay: Option<&[u8]>,
+    directory: Option<&DirectoryMetadata>,
+    backend: &mut dyn ResourceBackend,
+) -> Result<()> {
+    for change in changes {
+        match &change.data {
+            Some(data) => backend.update(&change.kind, &change.name, change.lang, data)?,
+            None => backend.delete(&change.kind, &change.name, change.lang)?,
+        }
+        if let Some(metadata) = &change.metadata {
+            backend.set_metadata(&change.kind, &change.name, change.lang, metadata)?;
+        }
+    }
+    if let Some(overlay) = overlay {
+        backend.set_overlay(overlay)?;
+    }
+    if let Some(directory) = directory {
+        backend.set_directory(directory)?;
+    }
+    Ok(())
+}
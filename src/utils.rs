@@ -1,65 +1,94 @@
+use crate::result::Result;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(all(windows, feature = "win32"))]
 use windows::Win32::Foundation::{GetLastError, WIN32_ERROR};
 
-/// Convert a string to a zero-terminated [`windows::core::PCSTR`] string.
-#[macro_export]
-macro_rules! pcstr {
-    ($s:expr) => {
-        windows::core::PCSTR::from_raw(format!("{}\0", $s).as_ptr())
-    };
-}
-pub(crate) use pcstr;
-
 /// Get last windows error code
+#[cfg(all(windows, feature = "win32"))]
 pub(crate) fn get_last_error() -> WIN32_ERROR {
     unsafe { GetLastError() }
 }
 
-// /// Convert utf16 string to a zero-terminated `Vec<u16>`
-// pub fn utf16sz_to_u16vec(text: &String) -> Vec<u16> {
-//     let len = text.len()+1;
-//     let mut vec: Vec<u16> = Vec::with_capacity(len);
-//     vec.resize(len,0);
-//     for c in text.chars() {
-//         // TODO - proper encoding
-//         // let buf = [0;2];
-//         // c.encode_utf16(&mut buf);
-//         vec.push(c as u16);
-//     }
-//     vec.push(0);
-//     vec
-// }
+/// Convert a string to a zero-terminated UTF-16 `Vec<u16>`.
+pub(crate) fn string_to_u16vec_sz(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
 
 /// This function convers a string to a zero-terminated
-/// `u16` unicode string represented by a `Vec<u8>` buffer.
-pub(crate) fn string_to_u8vec_sz(text: &String) -> Vec<u8> {
-    let len = text.len() + 1;
-    let mut u16vec: Vec<u16> = Vec::with_capacity(len);
-    // u16vec.resize(len,0);
-    for c in text.chars() {
-        // TODO - proper encoding
-        // let buf = [0;2];
-        // c.encode_utf16(&mut buf);
-        u16vec.push(c as u16);
-    }
-    u16vec.push(0);
-    let len = len * 2;
-    let mut u8vec = vec![0; len];
-    let src = unsafe { std::mem::transmute(u16vec.as_ptr()) };
-    let dest = u8vec[0..].as_mut_ptr();
-    unsafe {
-        std::ptr::copy(src, dest, len);
-    }
-    u8vec
+/// UTF-16LE unicode string represented by a `Vec<u8>` buffer.
+pub(crate) fn string_to_u8vec_sz(text: &str) -> Vec<u8> {
+    string_to_u16vec_sz(text)
+        .into_iter()
+        .flat_map(u16::to_le_bytes)
+        .collect()
 }
 
 /// Convert `u32` (DWORD) slice to a `Vec<u8>` buffer.
 pub(crate) fn u32slice_to_u8vec(u32slice: &[u32]) -> Vec<u8> {
     let len = u32slice.len() * 4;
     let mut u8vec = vec![0; len];
-    let src = unsafe { std::mem::transmute(u32slice.as_ptr()) };
+    let src = u32slice.as_ptr() as *const u8;
     let dest = u8vec[0..].as_mut_ptr();
     unsafe {
         std::ptr::copy(src, dest, len);
     }
     u8vec
 }
+
+/// Obtain a `len` byte sub-slice of `data` located at `offset`,
+/// returning an error if the range falls outside of the buffer.
+pub(crate) fn try_slice(data: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    match offset.checked_add(len) {
+        Some(last) if last <= data.len() => Ok(&data[offset..last]),
+        _ => Err(format!(
+            "range {offset}..{} is out of bounds[0..{}]",
+            offset.saturating_add(len),
+            data.len()
+        )
+        .into()),
+    }
+}
+
+/// Round `value` up to the nearest multiple of `alignment`.
+pub(crate) fn align(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Path of a unique temporary file located next to `file`.
+pub(crate) fn temp_file_path(file: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let index = COUNTER.fetch_add(1, Ordering::Relaxed);
+    file.with_file_name(format!(".{name}.{}-{index}.tmp", std::process::id()))
+}
+
+/// Path of the backup copy of `file` (`<file>.bak`).
+pub(crate) fn backup_file_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+/// Write `data` to a temporary file located next to `file` and rename it
+/// into place, so that `file` is never left partially written.
+pub(crate) fn write_file_atomic(file: &Path, data: &[u8]) -> Result<()> {
+    let temp = temp_file_path(file);
+    let result = (|| -> std::io::Result<()> {
+        let mut dest = std::fs::File::create(&temp)?;
+        dest.write_all(data)?;
+        dest.sync_all()?;
+        if let Ok(metadata) = std::fs::metadata(file) {
+            std::fs::set_permissions(&temp, metadata.permissions())?;
+        }
+        std::fs::rename(&temp, file)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    Ok(result?)
+}
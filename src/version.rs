@@ -1,4 +1,5 @@
 use crate::error::Error;
+use crate::lang::LangId;
 use crate::resources::Resource;
 use crate::result::Result;
 use crate::utils::*;
@@ -45,7 +46,7 @@ impl TrySerialize for Header {
                 dest.try_store_u16le(1)?;
             }
         };
-        dest.try_store_utf16le_sz(&self.key)?;
+        dest.try_store_u16le_slice(&string_to_u16vec_sz(&self.key))?;
         dest.try_align_u32()?;
         Ok(())
     }
@@ -427,11 +428,10 @@ impl TryFrom<Arc<Resource>> for VersionInfo {
         src.try_offset(skip)?;
 
         let mut children = Vec::new();
-        let mut remaining = src.remaining();
-        while remaining > 0 {
+        // children are DWORD-aligned; trailing alignment padding is not a child
+        while src.remaining() > (4 - src.cursor() % 4) % 4 {
             let child: VersionInfoChild = src.try_load()?;
             children.push(child);
-            remaining = src.remaining();
         }
 
         let info = VersionInfo {
@@ -542,10 +542,14 @@ impl VersionInfo {
         self
     }
 
+    /// Ensure a string table exists for the supplied translation key
+    /// (language id and code page hex string such as `040904b0`).
+    /// Keys are compared case-insensitively, as resource compilers
+    /// typically emit uppercase keys such as `040904B0`.
     pub fn ensure_language(&mut self, lang: &str) -> &mut Self {
         for child in self.children.iter_mut() {
             if let VersionInfoChild::StringFileInfo { tables } = child {
-                if tables.get(lang).is_none() {
+                if !tables.keys().any(|key| key.eq_ignore_ascii_case(lang)) {
                     tables.insert(lang.to_string(), HashMap::new());
                 }
             }
@@ -553,6 +557,35 @@ impl VersionInfo {
         self
     }
 
+    /// Ensure a string table exists for the supplied language and code page
+    /// (e.g. `LangId::EN_US` and `1200` for the `040904b0` table).
+    pub fn ensure_lang_id(&mut self, lang: LangId, code_page: u16) -> &mut Self {
+        self.ensure_language(&format!("{:04x}{:04x}", lang.0, code_page))
+    }
+
+    /// Languages and code pages of the string tables. Tables with keys
+    /// that are not valid translation keys are omitted.
+    pub fn languages(&self) -> Vec<(LangId, u16)> {
+        let mut languages = Vec::new();
+        for child in &self.children {
+            if let VersionInfoChild::StringFileInfo { tables } = child {
+                for key in tables.keys() {
+                    if key.len() != 8 || !key.is_ascii() {
+                        continue;
+                    }
+                    if let (Ok(lang), Ok(code_page)) = (
+                        u16::from_str_radix(&key[0..4], 16),
+                        u16::from_str_radix(&key[4..8], 16),
+                    ) {
+                        languages.push((LangId(lang), code_page));
+                    }
+                }
+            }
+        }
+        languages.sort();
+        languages
+    }
+
     pub fn update(&mut self) -> Result<()> {
         self.resource.replace(&self.try_to_vec()?)?.update()?;
         Ok(())
@@ -0,0 +1,399 @@
+//!
+//! Resource backend based on the Win32 resource API.
+//!
+
+use crate::backend::{ResourceBackend, Storage};
+use crate::data::Mapping;
+use crate::encoding::WideString;
+use crate::error::Error;
+use crate::id::Id;
+use crate::pe::{self, PeImage, SignaturePolicy};
+use crate::resources::{ResourceEntry, ResourceMetadata};
+use crate::result::Result;
+use crate::rsrc::DirectoryMetadata;
+use crate::utils::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use windows::{
+    core::PCWSTR,
+    Win32::Foundation::{BOOL, HANDLE, HINSTANCE},
+    Win32::System::LibraryLoader::*,
+};
+
+/// Backend using `LoadLibraryExW` with the resource enumeration functions for
+/// loading and `BeginUpdateResourceW` / `UpdateResourceW` / `EndUpdateResourceW`
+/// for updates. Updates are applied to a working copy of the file which
+/// replaces the file once the changes are committed.
+#[derive(Debug)]
+pub struct Win32Backend {
+    file: PathBuf,
+    handle: Option<HANDLE>,
+    /// working copy of the file updated during the update session
+    working: Option<PathBuf>,
+    /// overlay to be retained (or replaced) on commit
+    overlay: Option<Vec<u8>>,
+    /// replacement of the resource directory attributes staged during the update session
+    directory: Option<DirectoryMetadata>,
+    /// resource attributes staged during the update session
+    metadata: Vec<(Id, Id, u16, ResourceMetadata)>,
+    signature_policy: SignaturePolicy,
+    update_checksum: bool,
+    deterministic: bool,
+}
+
+impl Win32Backend {
+    /// Create a Win32 backend bound to the supplied file.
+    pub fn new(file: &Path) -> Win32Backend {
+        Win32Backend {
+            file: file.to_path_buf(),
+            handle: None,
+            working: None,
+            overlay: None,
+            directory: None,
+            metadata: Vec::new(),
+            signature_policy: SignaturePolicy::default(),
+            update_checksum: true,
+            deterministic: false,
+        }
+    }
+
+    fn not_open(&self) -> Error {
+        format!("resource file '{}' is not open", self.file.display()).into()
+    }
+
+    fn handle(&self) -> Result<HANDLE> {
+        self.handle.ok_or_else(|| self.not_open())
+    }
+
+    fn remove_working_copy(&mut self) {
+        if let Some(working) = self.working.take() {
+            let _ = std::fs::remove_file(working);
+        }
+    }
+
+    fn update_impl(&self, kind: &Id, name: &Id, lang: u16, data: Option<&[u8]>) -> Result<()> {
+        // the encoded ids must outlive the call
+        let kind = kind.to_wide();
+        let name = name.to_wide();
+        let success = unsafe {
+            UpdateResourceW(
+                self.handle()?,
+                kind.as_pcwstr(),
+                name.as_pcwstr(),
+                lang,
+                data.map(|data| data.as_ptr() as *const std::ffi::c_void),
+                data.map(|data| data.len() as u32).unwrap_or(0),
+            )
+            .as_bool()
+        };
+
+        if !success {
+            return Err(format!("Error updating resources: {:?}", get_last_error()).into());
+        }
+
+        Ok(())
+    }
+
+    fn end(&mut self, discard: bool) -> Result<()> {
+        if let Some(handle) = self.handle.take() {
+            unsafe { EndUpdateResourceW(handle, discard) }.ok()?;
+        }
+        Ok(())
+    }
+
+    /// End the update session applying the changes and return the updated file.
+    fn end_and_read(&mut self) -> Result<Vec<u8>> {
+        self.end(false)?;
+
+        // EndUpdateResourceW does not retain the overlay, strip the certificate
+        // table or update the checksum, and does not store resource attributes
+        let working = self.working.as_ref().ok_or_else(|| self.not_open())?;
+        let mut updated = std::fs::read(working)?;
+        if let Some(rebuilt) = self.apply_metadata(&updated)? {
+            updated = rebuilt;
+        }
+        if let Some(overlay) = self.overlay.take() {
+            let image = PeImage::parse(&updated)?;
+            if image.overlay()? != overlay.as_slice() {
+                updated = image.with_overlay(&overlay)?;
+            }
+        }
+        if self.signature_policy == SignaturePolicy::Strip {
+            updated = PeImage::parse(&updated)?.without_certificate_table()?;
+        }
+        if self.update_checksum {
+            pe::update_checksum(&mut updated)?;
+        }
+        Ok(updated)
+    }
+
+    /// Apply the staged attributes of the resource directory and of the
+    /// resources, which `EndUpdateResourceW` does not store. Returns the image
+    /// with a rebuilt resource section if the staged attributes differ from
+    /// those of the updated image; the code pages of the resources retained
+    /// from the original file are restored in that case.
+    fn apply_metadata(&mut self, updated: &[u8]) -> Result<Option<Vec<u8>>> {
+        let staged: HashMap<_, _> = std::mem::take(&mut self.metadata)
+            .into_iter()
+            .map(|(kind, name, lang, metadata)| ((kind, name, lang), metadata))
+            .collect();
+        let staged_directory = self.directory.take();
+        if staged.is_empty() && staged_directory.is_none() && !self.deterministic {
+            return Ok(None);
+        }
+
+        let image = PeImage::parse(updated)?;
+        let current = image.resource_directory()?.unwrap_or_default();
+        let (original_directory, originals) = read_metadata(&self.file).unwrap_or_default();
+        let mut directory = staged_directory.unwrap_or(original_directory);
+        if self.deterministic {
+            directory.time_date_stamp = 0;
+        }
+        let mut changed = match staged_directory {
+            Some(_) => current != directory,
+            None => self.deterministic && current.time_date_stamp != 0,
+        };
+
+        let mut entries = image.resources()?;
+        for entry in entries.iter_mut() {
+            let key = (entry.kind.clone(), entry.name.clone(), entry.lang);
+            if let Some(metadata) = staged.get(&key) {
+                changed |= entry.metadata.code_page != metadata.code_page;
+                entry.metadata.code_page = metadata.code_page;
+            } else if let Some(original) = originals.get(&key) {
+                entry.metadata.code_page = original.code_page;
+            }
+        }
+
+        if !changed {
+            return Ok(None);
+        }
+        Ok(Some(image.rebuild(&entries, &directory, None)?))
+    }
+}
+
+impl ResourceBackend for Win32Backend {
+    fn load(&self) -> Result<Vec<ResourceEntry>> {
+        let mut enumeration = Enumeration::default();
+        let file = WideString::from_os_str(self.file.as_os_str());
+        unsafe {
+            let handle = LoadLibraryExW(
+                file.as_pcwstr(),
+                None,
+                // LOAD_LIBRARY_FLAGS::default()
+                DONT_RESOLVE_DLL_REFERENCES | LOAD_LIBRARY_AS_DATAFILE,
+            )?;
+
+            let ptr: *mut Enumeration = &mut enumeration;
+            let success = EnumResourceTypesW(handle, Some(enum_types), ptr as isize).as_bool();
+
+            FreeLibrary(handle);
+
+            if let Some(err) = enumeration.error.take() {
+                return Err(err);
+            }
+            if !success {
+                return Err(format!(
+                    "Resources::load(): Error enumerating resources: {:?}",
+                    get_last_error()
+                )
+                .into());
+            }
+        }
+
+        // the resource enumeration functions do not report code pages
+        let mut entries = enumeration.entries;
+        if let Some((_, originals)) = read_metadata(&self.file) {
+            for entry in entries.iter_mut() {
+                let key = (entry.kind.clone(), entry.name.clone(), entry.lang);
+                if let Some(metadata) = originals.get(&key) {
+                    entry.metadata = *metadata;
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn begin(&mut self, delete_existing_resources: bool) -> Result<()> {
+        let mapping = Mapping::map(&self.file)?;
+        let image = PeImage::parse(&mapping)?;
+        if self.signature_policy == SignaturePolicy::Refuse && image.is_signed() {
+            return Err(pe::signed_image_error(&self.file.display().to_string()));
+        }
+        self.overlay = Some(image.overlay()?.to_vec());
+
+        let working = temp_file_path(&self.file);
+        std::fs::copy(&self.file, &working)?;
+        let path = WideString::from_os_str(working.as_os_str());
+        let handle = unsafe { BeginUpdateResourceW(path.as_pcwstr(), delete_existing_resources) };
+        self.working.replace(working);
+        match handle {
+            Ok(handle) => {
+                self.handle.replace(handle);
+                Ok(())
+            }
+            Err(err) => {
+                self.remove_working_copy();
+                Err(err.into())
+            }
+        }
+    }
+
+    fn update(&mut self, kind: &Id, name: &Id, lang: u16, data: &[u8]) -> Result<()> {
+        self.update_impl(kind, name, lang, Some(data))
+    }
+
+    fn set_metadata(
+        &mut self,
+        kind: &Id,
+        name: &Id,
+        lang: u16,
+        metadata: &ResourceMetadata,
+    ) -> Result<()> {
+        self.handle()?;
+        self.metadata
+            .push((kind.clone(), name.clone(), lang, *metadata));
+        Ok(())
+    }
+
+    fn delete(&mut self, kind: &Id, name: &Id, lang: u16) -> Result<()> {
+        self.update_impl(kind, name, lang, None)
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        let storage = Storage::File(self.file.clone());
+        self.commit_to(&storage)
+    }
+
+    fn commit_to(&mut self, storage: &Storage) -> Result<()> {
+        if self.handle.is_none() {
+            return Ok(());
+        }
+        let updated = self.end_and_read();
+        self.remove_working_copy();
+        storage.write(updated?)
+    }
+
+    fn discard(&mut self) -> Result<()> {
+        self.overlay = None;
+        self.directory = None;
+        self.metadata.clear();
+        let result = self.end(true);
+        self.remove_working_copy();
+        result
+    }
+
+    fn set_overlay(&mut self, overlay: &[u8]) -> Result<()> {
+        self.handle()?;
+        self.overlay = Some(overlay.to_vec());
+        Ok(())
+    }
+
+    fn load_directory(&self) -> Result<Option<DirectoryMetadata>> {
+        let mapping = Mapping::map(&self.file)?;
+        PeImage::parse(&mapping)?.resource_directory()
+    }
+
+    fn set_directory(&mut self, directory: &DirectoryMetadata) -> Result<()> {
+        self.handle()?;
+        self.directory = Some(*directory);
+        Ok(())
+    }
+
+    fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    fn set_signature_policy(&mut self, policy: SignaturePolicy) {
+        self.signature_policy = policy;
+    }
+
+    fn set_update_checksum(&mut self, update_checksum: bool) {
+        self.update_checksum = update_checksum;
+    }
+}
+
+/// Attributes of a resource keyed by its type, name and language.
+type MetadataMap = HashMap<(Id, Id, u16), ResourceMetadata>;
+
+/// Read the attributes of the resource directory and of the resources of the
+/// image `file` through a memory mapping (resource data is not copied).
+/// Returns `None` if the image cannot be parsed: the Win32 loader accepts
+/// images the pure-Rust reader may reject, which then have no attributes.
+fn read_metadata(file: &Path) -> Option<(DirectoryMetadata, MetadataMap)> {
+    let mapping = Mapping::map(file).ok()?;
+    let image = PeImage::parse(&mapping).ok()?;
+    let directory = image.resource_directory().ok()?.unwrap_or_default();
+    let entries = image.resources_with(Some(&mapping)).ok()?;
+    let metadata = entries
+        .into_iter()
+        .map(|entry| ((entry.kind, entry.name, entry.lang), entry.metadata))
+        .collect();
+    Some((directory, metadata))
+}
+
+/// Context of the resource enumeration callbacks. Errors are recorded here
+/// and stop the enumeration, as unwinding out of a callback is not possible.
+#[derive(Default)]
+struct Enumeration {
+    entries: Vec<ResourceEntry>,
+    error: Option<Error>,
+}
+
+unsafe extern "system" fn enum_languages(
+    hmodule: HINSTANCE,
+    lptype: PCWSTR,
+    lpname: PCWSTR,
+    lang: u16,
+    lparam: isize,
+) -> BOOL {
+    let enumeration = &mut *(lparam as *mut Enumeration);
+    let hresinfo = FindResourceExW(hmodule, lptype, lpname, lang);
+    let data = if hresinfo.is_invalid() {
+        None
+    } else {
+        let len = SizeofResource(hmodule, hresinfo) as usize;
+        let data_ptr = LockResource(LoadResource(hmodule, hresinfo));
+        match (data_ptr.is_null(), len) {
+            (_, 0) => Some(&[][..]),
+            (false, len) => Some(std::slice::from_raw_parts(data_ptr as *const u8, len)),
+            (true, _) => None,
+        }
+    };
+    let kind = Id::from(lptype);
+    let name = Id::from(lpname);
+    match data {
+        Some(data) => {
+            enumeration
+                .entries
+                .push(ResourceEntry::new(kind, name, lang, data));
+            BOOL(1)
+        }
+        None => {
+            enumeration.error = Some(
+                format!(
+                    "Resources::load(): unable to load resource {kind}/{name}/{lang}: {:?}",
+                    get_last_error()
+                )
+                .into(),
+            );
+            BOOL(0)
+        }
+    }
+}
+
+unsafe extern "system" fn enum_names(
+    hmodule: HINSTANCE,
+    lptype: PCWSTR,
+    lpname: PCWSTR,
+    lparam: isize,
+) -> BOOL {
+    EnumResourceLanguagesW(hmodule, lptype, lpname, Some(enum_languages), lparam);
+    BOOL((*(lparam as *const Enumeration)).error.is_none() as i32)
+}
+
+unsafe extern "system" fn enum_types(hmodule: HINSTANCE, lptype: PCWSTR, lparam: isize) -> BOOL {
+    EnumResourceNamesW(hmodule, lptype, Some(enum_names), lparam);
+    BOOL((*(lparam as *const Enumeration)).error.is_none() as i32)
+}
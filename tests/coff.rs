@@ -0,0 +1,243 @@
+mod common;
+
+use common::*;
+use winres_edit::coff::{self, Machine};
+use winres_edit::pe::PeImage;
+use winres_edit::*;
+
+fn sample_entries() -> Vec<ResourceEntry> {
+    vec![
+        ResourceEntry::new(resource_type::VERSION, Id::Integer(1), 1033, &[0xaa; 10]),
+        ResourceEntry::new(Id::Integer(10), Id::Text("MYDATA".into()), 1033, b"hello"),
+        ResourceEntry::new(
+            Id::Text("CUSTOM".into()),
+            Id::Text("X".into()),
+            0,
+            &[1, 2, 3],
+        ),
+    ]
+}
+
+fn u16le(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32le(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+struct ObjectSection {
+    name: String,
+    data: Vec<u8>,
+    relocations: Vec<(u32, u32, u16)>,
+}
+
+fn object_sections(object: &[u8]) -> Vec<ObjectSection> {
+    let count = u16le(object, 2) as usize;
+    (0..count)
+        .map(|index| {
+            let header = &object[20 + index * 40..20 + (index + 1) * 40];
+            let name = String::from_utf8_lossy(&header[0..8])
+                .trim_end_matches('\0')
+                .to_string();
+            let size = u32le(header, 16) as usize;
+            let pointer = u32le(header, 20) as usize;
+            let relocations_pointer = u32le(header, 24) as usize;
+            let relocations = (0..u16le(header, 32) as usize)
+                .map(|index| {
+                    let offset = relocations_pointer + index * coff::IMAGE_RELOCATION_SIZE;
+                    (
+                        u32le(object, offset),
+                        u32le(object, offset + 4),
+                        u16le(object, offset + 8),
+                    )
+                })
+                .collect();
+            ObjectSection {
+                name,
+                data: object[pointer..pointer + size].to_vec(),
+                relocations,
+            }
+        })
+        .collect()
+}
+
+/// Emulate the linker merging `.rsrc$01` and `.rsrc$02` into a `.rsrc`
+/// section located at `rva`.
+fn link_resources(object: &[u8], rva: u32) -> Vec<u8> {
+    let sections = object_sections(object);
+    let mut section = sections[0].data.clone();
+    section.resize(section.len().div_ceil(8) * 8, 0);
+    let data_rva = rva + section.len() as u32;
+    for (offset, _, _) in &sections[0].relocations {
+        let offset = *offset as usize;
+        let value = u32le(&section, offset) + data_rva;
+        section[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    section.extend_from_slice(&sections[1].data);
+    section
+}
+
+#[test]
+fn write_resource_object() {
+    for (machine, value, relocation) in [
+        (Machine::X86, IMAGE_FILE_MACHINE_I386, 0x0007),
+        (Machine::X64, IMAGE_FILE_MACHINE_AMD64, 0x0003),
+        (Machine::Arm64, IMAGE_FILE_MACHINE_ARM64, 0x0002),
+        (Machine::Arm64EC, 0xa641, 0x0002),
+    ] {
+        let object = coff::write_object(&sample_entries(), machine).unwrap();
+        assert_eq!(u16le(&object, 0), value);
+        assert_eq!(Machine::try_from(value).unwrap(), machine);
+
+        let sections = object_sections(&object);
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].name, ".rsrc$01");
+        assert_eq!(sections[1].name, ".rsrc$02");
+        assert_eq!(sections[0].relocations.len(), 3);
+        assert!(sections[1].relocations.is_empty());
+        for (_, symbol, kind) in &sections[0].relocations {
+            // .rsrc$02 section symbol
+            assert_eq!(*symbol, 3);
+            assert_eq!(*kind, relocation);
+        }
+    }
+    assert!(Machine::try_from(0x1234).is_err());
+}
+
+#[test]
+fn linked_resource_object_is_readable() {
+    let object = coff::write_object(&sample_entries(), Machine::X64).unwrap();
+    let mut section = SectionSpec::data(".rsrc", vec![]);
+    section.content = Box::new(move |rva| link_resources(&object, rva));
+    section.directory = Some(2);
+    let image = build_image(&ImageSpec::default(), &[SectionSpec::code(0x100), section]);
+
+    let mut entries = PeImage::parse(&image).unwrap().resources().unwrap();
+    let mut expected = sample_entries();
+    let key = |entry: &ResourceEntry| format!("{:?}/{:?}", entry.kind, entry.name);
+    entries.sort_by_key(key);
+    expected.sort_by_key(key);
+    assert_eq!(entries.len(), expected.len());
+    for (entry, expected) in entries.iter().zip(expected.iter()) {
+        assert_eq!(entry.kind, expected.kind);
+        assert_eq!(entry.name, expected.name);
+        assert_eq!(entry.lang, expected.lang);
+        assert_eq!(entry.data, expected.data);
+    }
+}
+
+#[test]
+fn save_resources_as_object_file() {
+    let res = temp_path("object.res");
+    let obj = temp_path("object.obj");
+    let entries = sample_entries()
+        .into_iter()
+        .map(|entry| (entry.kind, entry.name, entry.lang, entry.data.into_vec()))
+        .collect::<Vec<_>>();
+    std::fs::write(&res, build_res(&entries)).unwrap();
+
+    let resources = Resources::new(&res);
+    resources.load().unwrap();
+    resources.save_coff(&obj, Machine::Arm64).unwrap();
+    let object = std::fs::read(&obj).unwrap();
+    assert_eq!(
+        object,
+        coff::write_object(&sample_entries(), Machine::Arm64).unwrap()
+    );
+
+    std::fs::remove_file(&res).unwrap();
+    std::fs::remove_file(&obj).unwrap();
+}
+
+fn archive_member(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut member = format!(
+        "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+        name,
+        0,
+        0,
+        0,
+        644,
+        data.len()
+    )
+    .into_bytes();
+    member.extend_from_slice(data);
+    if member.len() % 2 != 0 {
+        member.push(b'\n');
+    }
+    member
+}
+
+fn sample_archive() -> Vec<u8> {
+    let mut archive = coff::IMAGE_ARCHIVE_START.to_vec();
+    // linker member and an (odd sized) non-object member are skipped
+    archive.extend(archive_member("/", &[0; 4]));
+    archive.extend(archive_member("readme.txt/", b"odd"));
+    archive.extend(archive_member(
+        "a.obj/",
+        &coff::write_object(&sample_entries()[..1], Machine::X64).unwrap(),
+    ));
+    archive.extend(archive_member(
+        "b.obj/",
+        &coff::write_object(&sample_entries()[1..], Machine::X64).unwrap(),
+    ));
+    archive
+}
+
+#[test]
+fn load_resources_from_object_file() {
+    let path = temp_path("load.obj");
+    std::fs::write(
+        &path,
+        coff::write_object(&sample_entries(), Machine::X86).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(BackendKind::detect(&path).unwrap(), BackendKind::Coff);
+
+    let resources = Resources::new(&path);
+    resources.load().unwrap();
+    assert_eq!(resources.list.lock().unwrap().len(), 3);
+    let data = resources
+        .find(Id::Integer(10), Id::Text("MYDATA".into()))
+        .unwrap();
+    assert_eq!(*data.encoded.lock().unwrap(), b"hello".to_vec());
+
+    // object files can not be modified
+    let mut resources = Resources::new(&path);
+    assert!(resources.open().is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn load_resources_from_archive() {
+    let entries = coff::read_archive(&sample_archive()).unwrap();
+    assert_eq!(entries.len(), 3);
+
+    let path = temp_path("load.lib");
+    std::fs::write(&path, sample_archive()).unwrap();
+    assert_eq!(BackendKind::detect(&path).unwrap(), BackendKind::Coff);
+    let resources = Resources::new(&path);
+    resources.load().unwrap();
+    assert_eq!(resources.list.lock().unwrap().len(), 3);
+    assert!(resources
+        .find(Id::Text("CUSTOM".into()), Id::Text("X".into()))
+        .is_some());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn malformed_objects_are_rejected() {
+    let object = coff::write_object(&sample_entries(), Machine::X64).unwrap();
+    assert!(coff::is_object_file(&object));
+    assert!(coff::read_resources(&object[..object.len() / 2]).is_err());
+
+    // relocation of a data entry removed
+    let mut object = object;
+    object[52..54].copy_from_slice(&2u16.to_le_bytes());
+    assert!(coff::read_resources(&object).is_err());
+
+    let archive = sample_archive();
+    assert!(coff::read_archive(&archive[..archive.len() - 8]).is_err());
+}
@@ -0,0 +1,513 @@
+//!
+//! Helpers for building synthetic PE images used by the integration tests.
+//!
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use winres_edit::Id;
+
+pub const IMAGE_FILE_MACHINE_I386: u16 = 0x014c;
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+pub const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+pub const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
+pub const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+
+/// Layout parameters of a synthetic image
+#[derive(Debug, Clone)]
+pub struct ImageSpec {
+    pub machine: u16,
+    pub pe32_plus: bool,
+    pub file_alignment: u32,
+    pub section_alignment: u32,
+    pub number_of_rva_and_sizes: u32,
+}
+
+impl Default for ImageSpec {
+    fn default() -> Self {
+        ImageSpec {
+            machine: IMAGE_FILE_MACHINE_AMD64,
+            pe32_plus: true,
+            file_alignment: 0x200,
+            section_alignment: 0x1000,
+            number_of_rva_and_sizes: 16,
+        }
+    }
+}
+
+/// Section of a synthetic image. The content is produced by a closure
+/// receiving the RVA assigned to the section.
+pub struct SectionSpec {
+    pub name: &'static str,
+    pub characteristics: u32,
+    pub content: Box<dyn Fn(u32) -> Vec<u8>>,
+    /// data directory index pointing to the start of this section
+    pub directory: Option<usize>,
+}
+
+impl SectionSpec {
+    pub fn code(len: usize) -> SectionSpec {
+        SectionSpec {
+            name: ".text",
+            characteristics: IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+            content: Box::new(move |_| vec![0xcc; len]),
+            directory: None,
+        }
+    }
+
+    pub fn data(name: &'static str, content: Vec<u8>) -> SectionSpec {
+        SectionSpec {
+            name,
+            characteristics: IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+            content: Box::new(move |_| content.clone()),
+            directory: None,
+        }
+    }
+
+    pub fn resources(entries: Vec<(Id, Id, u16, Vec<u8>)>) -> SectionSpec {
+        SectionSpec {
+            name: ".rsrc",
+            characteristics: IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+            content: Box::new(move |rva| build_rsrc(rva, &entries)),
+            directory: Some(2),
+        }
+    }
+}
+
+fn align(v: usize, alignment: usize) -> usize {
+    v.div_ceil(alignment) * alignment
+}
+
+fn put_u16(buf: &mut [u8], offset: usize, v: u16) {
+    buf[offset..offset + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, v: u32) {
+    buf[offset..offset + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], offset: usize, v: u64) {
+    buf[offset..offset + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+/// Build a PE image with the supplied sections.
+pub fn build_image(spec: &ImageSpec, sections: &[SectionSpec]) -> Vec<u8> {
+    let pe_offset = 0x80;
+    let optional_header_size =
+        if spec.pe32_plus { 112 } else { 96 } + spec.number_of_rva_and_sizes as usize * 8;
+    let section_table = pe_offset + 24 + optional_header_size;
+    let headers_len = section_table + sections.len() * 40;
+    let size_of_headers = align(headers_len, spec.file_alignment as usize);
+
+    let mut image = vec![0u8; size_of_headers];
+    let mut rva = align(size_of_headers, spec.section_alignment as usize);
+    let mut directories = Vec::new();
+    for (index, section) in sections.iter().enumerate() {
+        let content = (section.content)(rva as u32);
+        let raw_size = align(content.len(), spec.file_alignment as usize);
+        let raw_ptr = image.len();
+        image.extend_from_slice(&content);
+        image.resize(raw_ptr + raw_size, 0);
+
+        let header = section_table + index * 40;
+        image[header..header + section.name.len()].copy_from_slice(section.name.as_bytes());
+        put_u32(&mut image, header + 8, content.len() as u32);
+        put_u32(&mut image, header + 12, rva as u32);
+        put_u32(&mut image, header + 16, raw_size as u32);
+        put_u32(&mut image, header + 20, raw_ptr as u32);
+        put_u32(&mut image, header + 36, section.characteristics);
+
+        if let Some(directory) = section.directory {
+            directories.push((directory, rva as u32, content.len() as u32));
+        }
+        rva += align(content.len().max(1), spec.section_alignment as usize);
+    }
+
+    put_u16(&mut image, 0, 0x5a4d);
+    put_u32(&mut image, 0x3c, pe_offset as u32);
+    put_u32(&mut image, pe_offset, 0x0000_4550);
+    let fh = pe_offset + 4;
+    put_u16(&mut image, fh, spec.machine);
+    put_u16(&mut image, fh + 2, sections.len() as u16);
+    put_u16(&mut image, fh + 16, optional_header_size as u16);
+    put_u16(&mut image, fh + 18, 0x0022);
+
+    let oh = fh + 20;
+    put_u16(&mut image, oh, if spec.pe32_plus { 0x20b } else { 0x10b });
+    if spec.pe32_plus {
+        put_u64(&mut image, oh + 24, 0x1_4000_0000);
+    } else {
+        put_u32(&mut image, oh + 28, 0x40_0000);
+    }
+    put_u32(&mut image, oh + 32, spec.section_alignment);
+    put_u32(&mut image, oh + 36, spec.file_alignment);
+    put_u16(&mut image, oh + 40, 6);
+    put_u16(&mut image, oh + 48, 6);
+    put_u32(&mut image, oh + 56, rva as u32);
+    put_u32(&mut image, oh + 60, size_of_headers as u32);
+    put_u16(&mut image, oh + 68, 3);
+    let rva_and_sizes = if spec.pe32_plus { oh + 108 } else { oh + 92 };
+    put_u32(&mut image, rva_and_sizes, spec.number_of_rva_and_sizes);
+    for (index, rva, size) in directories {
+        if (index as u32) < spec.number_of_rva_and_sizes {
+            put_u32(&mut image, rva_and_sizes + 4 + index * 8, rva);
+            put_u32(&mut image, rva_and_sizes + 8 + index * 8, size);
+        }
+    }
+
+    image
+}
+
+/// Resource names of a single type paired with indices of their language entries
+type NameGroups = Vec<(Id, Vec<usize>)>;
+
+/// Build a resource section for the supplied `(type, name, lang, data)` tuples.
+/// Entries must be supplied in the order expected in the directory tree.
+pub fn build_rsrc(rva: u32, entries: &[(Id, Id, u16, Vec<u8>)]) -> Vec<u8> {
+    // group entries by type and name preserving order
+    let mut types: Vec<(Id, NameGroups)> = Vec::new();
+    for (index, (kind, name, _, _)) in entries.iter().enumerate() {
+        if types.last().map(|(k, _)| k != kind).unwrap_or(true) {
+            types.push((kind.clone(), Vec::new()));
+        }
+        let names = &mut types.last_mut().unwrap().1;
+        if names.last().map(|(n, _)| n != name).unwrap_or(true) {
+            names.push((name.clone(), Vec::new()));
+        }
+        names.last_mut().unwrap().1.push(index);
+    }
+
+    let dir_len = |n: usize| 16 + n * 8;
+    let mut tables_len = dir_len(types.len());
+    for (_, names) in &types {
+        tables_len += dir_len(names.len());
+        for (_, langs) in names {
+            tables_len += dir_len(langs.len());
+        }
+    }
+
+    let mut strings = Vec::new();
+    let string_offset = |id: &Id, strings: &mut Vec<u8>| -> u32 {
+        match id {
+            Id::Integer(id) => *id as u32,
+            Id::Text(text) => {
+                let offset = tables_len + strings.len();
+                let units: Vec<u16> = text.encode_utf16().collect();
+                strings.extend_from_slice(&(units.len() as u16).to_le_bytes());
+                for unit in units {
+                    strings.extend_from_slice(&unit.to_le_bytes());
+                }
+                0x8000_0000 | offset as u32
+            }
+        }
+    };
+
+    let data_entries_offset = align(tables_len + 4096, 4);
+    let data_offset = data_entries_offset + entries.len() * 16;
+    let mut tree = vec![0u8; data_offset];
+    let mut data = Vec::new();
+
+    // lay out directories breadth-first: root, types, names
+    let mut cursor = dir_len(types.len());
+    let mut root_entries = Vec::new();
+    let mut type_dirs = Vec::new();
+    for (_, names) in &types {
+        type_dirs.push(cursor);
+        cursor += dir_len(names.len());
+    }
+    let mut name_dirs = Vec::new();
+    for (_, names) in &types {
+        let mut dirs = Vec::new();
+        for (_, langs) in names {
+            dirs.push(cursor);
+            cursor += dir_len(langs.len());
+        }
+        name_dirs.push(dirs);
+    }
+
+    let mut entry_index = 0;
+    for (t, (kind, names)) in types.iter().enumerate() {
+        root_entries.push((string_offset(kind, &mut strings), type_dirs[t]));
+        let mut name_entries = Vec::new();
+        for (n, (name, langs)) in names.iter().enumerate() {
+            name_entries.push((string_offset(name, &mut strings), name_dirs[t][n]));
+            let dir = name_dirs[t][n];
+            put_u16(&mut tree, dir + 14, langs.len() as u16);
+            for (l, index) in langs.iter().enumerate() {
+                let (_, _, lang, payload) = &entries[*index];
+                let data_entry = data_entries_offset + entry_index * 16;
+                put_u32(&mut tree, dir + 16 + l * 8, *lang as u32);
+                put_u32(&mut tree, dir + 20 + l * 8, data_entry as u32);
+                put_u32(
+                    &mut tree,
+                    data_entry,
+                    rva + (data_offset + data.len()) as u32,
+                );
+                put_u32(&mut tree, data_entry + 4, payload.len() as u32);
+                data.extend_from_slice(payload);
+                data.resize(align(data.len(), 8), 0);
+                entry_index += 1;
+            }
+        }
+        let dir = type_dirs[t];
+        let named = name_entries
+            .iter()
+            .filter(|(n, _)| n & 0x8000_0000 != 0)
+            .count();
+        put_u16(&mut tree, dir + 12, named as u16);
+        put_u16(&mut tree, dir + 14, (name_entries.len() - named) as u16);
+        for (i, (name, target)) in name_entries.iter().enumerate() {
+            put_u32(&mut tree, dir + 16 + i * 8, *name);
+            put_u32(&mut tree, dir + 20 + i * 8, 0x8000_0000 | *target as u32);
+        }
+    }
+    let named = root_entries
+        .iter()
+        .filter(|(n, _)| n & 0x8000_0000 != 0)
+        .count();
+    put_u16(&mut tree, 12, named as u16);
+    put_u16(&mut tree, 14, (root_entries.len() - named) as u16);
+    for (i, (name, target)) in root_entries.iter().enumerate() {
+        put_u32(&mut tree, 16 + i * 8, *name);
+        put_u32(&mut tree, 20 + i * 8, 0x8000_0000 | *target as u32);
+    }
+
+    assert!(strings.len() <= 4096);
+    tree[tables_len..tables_len + strings.len()].copy_from_slice(&strings);
+    tree.extend_from_slice(&data);
+    tree
+}
+
+/// Unique path in the system temporary directory
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("winres-edit-{}-{name}", std::process::id()))
+}
+
+fn push_res_id(dest: &mut Vec<u8>, id: &Id) {
+    match id {
+        Id::Integer(id) => {
+            dest.extend_from_slice(&0xffffu16.to_le_bytes());
+            dest.extend_from_slice(&id.to_le_bytes());
+        }
+        Id::Text(text) => {
+            for unit in text.encode_utf16().chain(std::iter::once(0)) {
+                dest.extend_from_slice(&unit.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Build a 32-bit `.res` file containing the supplied `(type, name, lang, data)` tuples.
+pub fn build_res(entries: &[(Id, Id, u16, Vec<u8>)]) -> Vec<u8> {
+    let mut res = vec![
+        0, 0, 0, 0, 0x20, 0, 0, 0, 0xff, 0xff, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0,
+    ];
+    for (kind, name, lang, data) in entries {
+        let mut header = Vec::new();
+        push_res_id(&mut header, kind);
+        push_res_id(&mut header, name);
+        header.resize(align(8 + header.len(), 4) - 8, 0);
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&0x1030u16.to_le_bytes());
+        header.extend_from_slice(&lang.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+
+        res.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        res.extend_from_slice(&(header.len() as u32 + 8).to_le_bytes());
+        res.extend_from_slice(&header);
+        res.extend_from_slice(data);
+        res.resize(align(res.len(), 4), 0);
+    }
+    res
+}
+
+/// Resource names of a single NE resource type paired with their data
+type NeNames<'a> = Vec<(&'a Id, &'a Vec<u8>)>;
+
+/// Build an NE image (or `.icl` icon library) containing the supplied
+/// `(type, name, data)` tuples, with resource data aligned to `1 << align_shift`.
+pub fn build_ne(entries: &[(Id, Id, Vec<u8>)], align_shift: u16) -> Vec<u8> {
+    let mut types: Vec<(&Id, NeNames)> = Vec::new();
+    for (kind, name, data) in entries {
+        match types.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, names)) => names.push((name, data)),
+            None => types.push((kind, vec![(name, data)])),
+        }
+    }
+
+    // resource table: rscAlignShift, TYPEINFO records, terminator, names
+    let records = types
+        .iter()
+        .map(|(_, names)| 8 + names.len() * 12)
+        .sum::<usize>();
+    let mut strings = Vec::new();
+    let string_id = |id: &Id, strings: &mut Vec<u8>| match id {
+        Id::Integer(id) => 0x8000 | id,
+        Id::Text(text) => {
+            let offset = (2 + records + 2 + strings.len()) as u16;
+            strings.push(text.len() as u8);
+            strings.extend_from_slice(text.as_bytes());
+            offset
+        }
+    };
+    let mut ids = Vec::new();
+    for (kind, names) in &types {
+        let names = names
+            .iter()
+            .map(|(name, _)| string_id(name, &mut strings))
+            .collect::<Vec<_>>();
+        ids.push((string_id(kind, &mut strings), names));
+    }
+    strings.push(0);
+    let table_size = 2 + records + 2 + strings.len();
+
+    let ne_offset = 0x40;
+    let table_offset = 0x40;
+    let alignment = 1usize << align_shift;
+    let mut data_offset = align(ne_offset + table_offset + table_size, alignment);
+
+    let mut table = align_shift.to_le_bytes().to_vec();
+    let mut blobs = Vec::new();
+    for ((_, names), (type_id, name_ids)) in types.iter().zip(ids.iter()) {
+        table.extend_from_slice(&type_id.to_le_bytes());
+        table.extend_from_slice(&(names.len() as u16).to_le_bytes());
+        table.extend_from_slice(&0u32.to_le_bytes());
+        for ((_, data), name_id) in names.iter().zip(name_ids.iter()) {
+            let length = align(data.len(), alignment);
+            table.extend_from_slice(&((data_offset >> align_shift) as u16).to_le_bytes());
+            table.extend_from_slice(&((length >> align_shift) as u16).to_le_bytes());
+            table.extend_from_slice(&0x0c50u16.to_le_bytes());
+            table.extend_from_slice(&name_id.to_le_bytes());
+            table.extend_from_slice(&0u32.to_le_bytes());
+            blobs.push((data_offset, *data));
+            data_offset += length;
+        }
+    }
+    table.extend_from_slice(&0u16.to_le_bytes());
+    table.extend_from_slice(&strings);
+
+    let mut image = vec![0u8; ne_offset + IMAGE_OS2_HEADER];
+    image[0..2].copy_from_slice(b"MZ");
+    put_u32(&mut image, 0x3c, ne_offset as u32);
+    image[ne_offset..ne_offset + 2].copy_from_slice(b"NE");
+    put_u16(&mut image, ne_offset + 0x24, table_offset as u16);
+    put_u16(
+        &mut image,
+        ne_offset + 0x26,
+        (table_offset + table_size) as u16,
+    );
+    image.extend_from_slice(&table);
+    // empty resident name table
+    image.push(0);
+    for (offset, data) in blobs {
+        image.resize(offset, 0);
+        image.extend_from_slice(data);
+    }
+    image.resize(data_offset, 0);
+    image
+}
+
+const IMAGE_OS2_HEADER: usize = 0x40;
+
+/// Build an icon group resource (`GRPICONDIR`) of `(width, height, bit_count,
+/// bytes_in_res, id)` images.
+pub fn build_icon_group(images: &[(u8, u8, u16, u32, u16)]) -> Vec<u8> {
+    let mut group = Vec::new();
+    group.extend_from_slice(&0u16.to_le_bytes());
+    group.extend_from_slice(&1u16.to_le_bytes());
+    group.extend_from_slice(&(images.len() as u16).to_le_bytes());
+    for (width, height, bit_count, bytes_in_res, id) in images {
+        group.extend_from_slice(&[*width, *height, 0, 0]);
+        group.extend_from_slice(&1u16.to_le_bytes());
+        group.extend_from_slice(&bit_count.to_le_bytes());
+        group.extend_from_slice(&bytes_in_res.to_le_bytes());
+        group.extend_from_slice(&id.to_le_bytes());
+    }
+    group
+}
+
+/// Append a certificate table containing the supplied data to the image
+/// and point the security data directory to it.
+pub fn append_certificate_table(image: &mut Vec<u8>, certificate: &[u8]) {
+    let pe_offset = u32::from_le_bytes(image[0x3c..0x40].try_into().unwrap()) as usize;
+    let oh = pe_offset + 24;
+    let pe32_plus = u16::from_le_bytes(image[oh..oh + 2].try_into().unwrap()) == 0x20b;
+    let directories = if pe32_plus { oh + 112 } else { oh + 96 };
+
+    image.resize(align(image.len(), 8), 0);
+    let offset = image.len() as u32;
+    // WIN_CERTIFICATE header: dwLength, wRevision, wCertificateType
+    let length = (8 + certificate.len()) as u32;
+    image.extend_from_slice(&length.to_le_bytes());
+    image.extend_from_slice(&0x0200u16.to_le_bytes());
+    image.extend_from_slice(&0x0002u16.to_le_bytes());
+    image.extend_from_slice(certificate);
+    image.resize(align(image.len(), 8), 0);
+    let size = image.len() as u32 - offset;
+    put_u32(image, directories + 4 * 8, offset);
+    put_u32(image, directories + 4 * 8 + 4, size);
+}
+
+fn utf16_sz(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect()
+}
+
+/// Build a version resource structure (`wLength`, `wValueLength`, `wType`,
+/// `szKey`, padding, value, padding, children).
+fn version_struct(
+    key: &str,
+    value_length: usize,
+    text: bool,
+    value: &[u8],
+    children: &[u8],
+) -> Vec<u8> {
+    let mut data = vec![0u8; 6];
+    put_u16(&mut data, 2, value_length as u16);
+    put_u16(&mut data, 4, text as u16);
+    data.extend(utf16_sz(key));
+    data.resize(align(data.len(), 4), 0);
+    data.extend_from_slice(value);
+    if !children.is_empty() {
+        data.resize(align(data.len(), 4), 0);
+        data.extend_from_slice(children);
+    }
+    let len = data.len() as u16;
+    put_u16(&mut data, 0, len);
+    data
+}
+
+/// Build a `VS_VERSIONINFO` resource with a single `040904b0` string table.
+pub fn build_version_info(version: [u16; 4], strings: &[(&str, &str)]) -> Vec<u8> {
+    let mut fixed = vec![0u8; 52];
+    put_u32(&mut fixed, 0, 0xfeef_04bd);
+    put_u32(&mut fixed, 4, 0x0001_0000);
+    for offset in [8, 16] {
+        put_u16(&mut fixed, offset, version[1]);
+        put_u16(&mut fixed, offset + 2, version[0]);
+        put_u16(&mut fixed, offset + 4, version[3]);
+        put_u16(&mut fixed, offset + 6, version[2]);
+    }
+
+    let mut table = Vec::new();
+    for (key, value) in strings {
+        table.resize(align(table.len(), 4), 0);
+        let value_length = value.encode_utf16().count() + 1;
+        table.extend(version_struct(
+            key,
+            value_length,
+            true,
+            &utf16_sz(value),
+            &[],
+        ));
+    }
+    let table = version_struct("040904b0", 0, true, &[], &table);
+    let string_file_info = version_struct("StringFileInfo", 0, true, &[], &table);
+    version_struct("VS_VERSION_INFO", 52, false, &fixed, &string_file_info)
+}
@@ -0,0 +1,99 @@
+//! Tests of the Win32 string and resource id encodings. These do not access
+//! the filesystem or call into the system and can be run under Miri
+//! (`cargo miri test --test encoding`).
+
+use winres_edit::*;
+
+#[test]
+fn ansi_string_is_zero_terminated() {
+    let text = AnsiString::new("MAINICON");
+    assert_eq!(text.as_bytes(), b"MAINICON");
+    let terminated = unsafe { std::slice::from_raw_parts(text.as_ptr(), 9) };
+    assert_eq!(terminated, b"MAINICON\0");
+
+    assert_eq!(AnsiString::new("caf\u{e9}").as_bytes(), b"caf\xe9");
+    assert_eq!(AnsiString::new("\u{418}\u{41a}").as_bytes(), b"??");
+    assert_eq!(AnsiString::new("").as_bytes(), b"");
+}
+
+#[test]
+fn wide_string_is_zero_terminated() {
+    let text = WideString::new("A\u{1f600}");
+    assert_eq!(text.as_units(), &[0x41, 0xd83d, 0xde00]);
+    let terminated = unsafe { std::slice::from_raw_parts(text.as_ptr(), 4) };
+    assert_eq!(terminated, &[0x41, 0xd83d, 0xde00, 0]);
+}
+
+#[cfg(windows)]
+#[test]
+fn wide_string_from_non_unicode_os_string() {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    // unpaired surrogate, valid in Windows paths
+    let path = OsString::from_wide(&[0x61, 0xd800, 0x2e, 0x65]);
+    assert!(path.to_str().is_none());
+    let text = WideString::from_os_str(&path);
+    assert_eq!(text.as_units(), &[0x61, 0xd800, 0x2e, 0x65]);
+}
+
+#[test]
+fn integer_ids_are_encoded_as_int_resources() {
+    let ansi = Id::Integer(14).to_ansi();
+    assert_eq!(ansi, AnsiId::Integer(14));
+    assert!(is_int_resource(ansi.as_ptr()));
+    assert_eq!(ansi.as_ptr() as usize, 14);
+
+    let wide = Id::Integer(0xffff).to_wide();
+    assert!(is_int_resource(wide.as_ptr()));
+    assert_eq!(wide.as_ptr() as usize, 0xffff);
+
+    assert_eq!(unsafe { Id::from_ansi_ptr(ansi.as_ptr()) }, Id::Integer(14));
+    assert_eq!(
+        unsafe { Id::from_wide_ptr(wide.as_ptr()) },
+        Id::Integer(0xffff)
+    );
+}
+
+#[test]
+fn text_ids_outlive_the_source_id() {
+    // the encoding owns its buffer: the pointer stays valid after the id is dropped
+    let ansi = {
+        let id = Id::Text("MAINICON".into());
+        id.to_ansi()
+    };
+    let wide = {
+        let id = Id::Text("MAINICON".into());
+        id.to_wide()
+    };
+    assert!(!is_int_resource(ansi.as_ptr()));
+    assert!(!is_int_resource(wide.as_ptr()));
+    assert_eq!(
+        unsafe { Id::from_ansi_ptr(ansi.as_ptr()) },
+        Id::Text("MAINICON".into())
+    );
+    assert_eq!(
+        unsafe { Id::from_wide_ptr(wide.as_ptr()) },
+        Id::Text("MAINICON".into())
+    );
+}
+
+#[test]
+fn decode_ids() {
+    let name = [0x4e, 0x41, 0x4d, 0x45, 0];
+    assert_eq!(
+        unsafe { Id::from_wide_ptr(name.as_ptr()) },
+        Id::Text("NAME".into())
+    );
+    // unpaired surrogates are replaced
+    let name = [0x41, 0xd800, 0];
+    assert_eq!(
+        unsafe { Id::from_wide_ptr(name.as_ptr()) },
+        Id::Text("A\u{fffd}".into())
+    );
+    // ANSI names are decoded as Latin-1
+    let name = [0x41u8, 0xe9, 0];
+    assert_eq!(
+        unsafe { Id::from_ansi_ptr(name.as_ptr()) },
+        Id::Text("A\u{e9}".into())
+    );
+}
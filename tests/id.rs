@@ -0,0 +1,141 @@
+use std::str::FromStr;
+use winres_edit::*;
+
+#[test]
+fn resource_type_conversions() {
+    assert_eq!(
+        ResourceType::from(resource_type::GROUP_ICON),
+        ResourceType::GroupIcon
+    );
+    assert_eq!(Id::from(ResourceType::Toolbar), Id::Integer(241));
+    assert_eq!(Id::from(ResourceType::RcData), resource_type::RCDATA);
+    assert_eq!(
+        ResourceType::from(Id::Integer(99)),
+        ResourceType::Integer(99)
+    );
+    assert_eq!(Id::from(ResourceType::Integer(99)), Id::Integer(99));
+    assert_eq!(
+        ResourceType::from(&Id::Text("MUI".into())),
+        ResourceType::Custom("MUI".into())
+    );
+    assert_eq!(
+        Id::from(ResourceType::Custom("MUI".into())),
+        Id::Text("MUI".into())
+    );
+    assert_eq!(ResourceType::String.to_integer(), Some(6));
+    assert_eq!(ResourceType::Custom("MUI".into()).to_integer(), None);
+}
+
+#[test]
+fn resource_type_display_and_parse() {
+    assert_eq!(ResourceType::GroupIcon.to_string(), "RT_GROUP_ICON");
+    assert_eq!(ResourceType::DlgInit.to_string(), "RT_DLGINIT");
+    assert_eq!(ResourceType::Integer(99).to_string(), "#99");
+    assert_eq!(ResourceType::Custom("MUI".into()).to_string(), "MUI");
+
+    let parse = |s: &str| ResourceType::from_str(s).unwrap();
+    assert_eq!(parse("RT_GROUP_ICON"), ResourceType::GroupIcon);
+    assert_eq!(parse("ICON"), ResourceType::Icon);
+    assert_eq!(parse("rt_manifest"), ResourceType::Manifest);
+    assert_eq!(parse("#14"), ResourceType::GroupIcon);
+    assert_eq!(parse("#99"), ResourceType::Integer(99));
+    assert_eq!(parse("MUI"), ResourceType::Custom("MUI".into()));
+    assert!(ResourceType::from_str("").is_err());
+    assert!(ResourceType::from_str("#x").is_err());
+
+    for kind in [ResourceType::Toolbar, ResourceType::Integer(500)] {
+        assert_eq!(parse(&kind.to_string()), kind);
+    }
+}
+
+#[test]
+fn text_ids_are_case_insensitive() {
+    use std::collections::HashSet;
+    let lower = Id::Text("myicon".into());
+    let upper = Id::Text("MYICON".into());
+    assert_eq!(lower, upper);
+    assert_ne!(lower, Id::Text("MYICON2".into()));
+    assert_ne!(Id::Text("1".into()), Id::Integer(1));
+    assert_eq!(Id::Text("иконка".into()), Id::Text("ИКОНКА".into()));
+
+    let set: HashSet<Id> = [lower, upper, Id::Integer(1)].into_iter().collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&Id::Text("MyIcon".into())));
+}
+
+#[test]
+fn ids_are_ordered_like_the_resource_directory() {
+    let mut ids = vec![
+        Id::Integer(10),
+        Id::Text("beta".into()),
+        Id::Integer(2),
+        Id::Text("ALPHA".into()),
+        Id::Text("Gamma".into()),
+    ];
+    ids.sort();
+    assert_eq!(
+        ids,
+        vec![
+            Id::Text("ALPHA".into()),
+            Id::Text("beta".into()),
+            Id::Text("Gamma".into()),
+            Id::Integer(2),
+            Id::Integer(10),
+        ]
+    );
+    // '_' (0x5f) sorts after uppercased letters
+    assert!(Id::Text("a_".into()) > Id::Text("AB".into()));
+}
+
+#[test]
+fn id_display_and_parse() {
+    assert_eq!(Id::Integer(123).to_string(), "#123");
+    assert_eq!(Id::Text("NAME".into()).to_string(), "NAME");
+    assert_eq!("#123".parse::<Id>().unwrap(), Id::Integer(123));
+    assert_eq!("NAME".parse::<Id>().unwrap(), Id::Text("NAME".into()));
+    assert!("#abc".parse::<Id>().is_err());
+    assert!("#70000".parse::<Id>().is_err());
+    assert!("".parse::<Id>().is_err());
+    for id in [Id::Integer(7), Id::Text("MAINICON".into())] {
+        assert_eq!(id.to_string().parse::<Id>().unwrap(), id);
+    }
+}
+
+#[test]
+fn text_ids_starting_with_hash_do_not_round_trip() {
+    let text = Id::Text("#12".into());
+    assert_eq!(text.to_string(), "#12");
+    assert_eq!(text.to_string().parse::<Id>().unwrap(), Id::Integer(12));
+    assert!(Id::Text("#ICON".into()).to_string().parse::<Id>().is_err());
+}
+
+#[test]
+fn resource_types_compare_like_ids() {
+    use std::collections::HashSet;
+    let lower = ResourceType::from(Id::Text("mui".into()));
+    let upper = ResourceType::from(Id::Text("MUI".into()));
+    assert_eq!(Id::Text("mui".into()), Id::Text("MUI".into()));
+    assert_eq!(lower, upper);
+    assert_ne!(lower, ResourceType::Custom("MUI2".into()));
+    assert_eq!(ResourceType::Integer(14), ResourceType::GroupIcon);
+    assert_ne!(ResourceType::Icon, ResourceType::GroupIcon);
+
+    let set: HashSet<ResourceType> = [lower, upper, ResourceType::Icon].into_iter().collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&ResourceType::Custom("Mui".into())));
+
+    let mut kinds = vec![
+        ResourceType::Manifest,
+        ResourceType::Custom("mui".into()),
+        ResourceType::Icon,
+    ];
+    kinds.sort();
+    assert_eq!(
+        kinds,
+        vec![
+            ResourceType::Custom("MUI".into()),
+            ResourceType::Icon,
+            ResourceType::Manifest,
+        ]
+    );
+}
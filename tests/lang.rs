@@ -0,0 +1,116 @@
+mod common;
+
+use common::*;
+use winres_edit::lang::*;
+use winres_edit::*;
+
+#[test]
+fn primary_and_sublanguage() {
+    let id = LangId(0x0c0a);
+    assert_eq!(id.primary(), 0x0a);
+    assert_eq!(id.sub(), 0x03);
+    assert_eq!(LangId::new(0x0a, 0x03), id);
+    assert_eq!(LangId::new(0x09, SUBLANG_DEFAULT), LangId::EN_US);
+    assert_eq!(LangId::NEUTRAL, LangId(0));
+    assert_eq!(LangId::INVARIANT, LangId(0x007f));
+    assert_eq!(LangId::new(LANG_NEUTRAL, SUBLANG_DEFAULT), LangId(0x0400));
+    assert_eq!(u16::from(LangId::from(1049)), 1049);
+}
+
+#[test]
+fn locale_names() {
+    assert_eq!(LangId(0x0409).tag(), Some("en-US"));
+    assert_eq!(LangId(0x0407).tag(), Some("de-DE"));
+    assert_eq!(LangId(0x0404).tag(), Some("zh-Hant-TW"));
+    assert_eq!(
+        LangId(0x0409).display_name(),
+        Some("English (United States)")
+    );
+    assert_eq!(
+        LangId(0x0404).display_name(),
+        Some("Chinese (Traditional, Taiwan)")
+    );
+    assert_eq!(LangId::NEUTRAL.display_name(), Some("Language Neutral"));
+    assert_eq!(LangId::INVARIANT.display_name(), Some("Invariant Language"));
+    assert_eq!(LangId::NEUTRAL.tag(), None);
+    assert_eq!(LangId(0x1234).display_name(), None);
+
+    assert_eq!(LangId::from_tag("de-DE"), Some(LangId(0x0407)));
+    assert_eq!(LangId::from_tag("ZH-hant-tw"), Some(LangId(0x0404)));
+    assert_eq!(LangId::from_tag("zh_TW"), Some(LangId(0x0404)));
+    assert_eq!(LangId::from_tag("xx-YY"), None);
+}
+
+#[test]
+fn display_and_parse() {
+    assert_eq!(LangId(0x0409).to_string(), "en-US");
+    assert_eq!(LangId(0x0404).to_string(), "zh-Hant-TW");
+    assert_eq!(LangId(0x1234).to_string(), "0x1234");
+    assert_eq!(LangId::NEUTRAL.to_string(), "0x0000");
+
+    assert_eq!("en-us".parse::<LangId>().unwrap(), LangId::EN_US);
+    assert_eq!("0x0419".parse::<LangId>().unwrap(), LangId(0x0419));
+    assert_eq!("1049".parse::<LangId>().unwrap(), LangId(0x0419));
+    assert!("klingon".parse::<LangId>().is_err());
+    assert!("0x10000".parse::<LangId>().is_err());
+    assert!("".parse::<LangId>().is_err());
+    for id in [LangId(0x0c0a), LangId(0x1234), LangId::INVARIANT] {
+        assert_eq!(id.to_string().parse::<LangId>().unwrap(), id);
+    }
+}
+
+#[test]
+fn version_info_languages() {
+    let entries = vec![(
+        resource_type::VERSION,
+        Id::Integer(1),
+        1033,
+        build_version_info([1, 0, 0, 0], &[("ProductName", "Product")]),
+    )];
+    let mut resources = Resources::from_bytes(&build_res(&entries));
+    resources.open().unwrap();
+    assert_eq!(
+        resources.list.lock().unwrap()[0].lang_id().to_string(),
+        "en-US"
+    );
+
+    let mut version = resources.get_version_info().unwrap().unwrap();
+    assert_eq!(version.languages(), vec![(LangId::EN_US, 1200)]);
+    let german: LangId = "de-DE".parse().unwrap();
+    version.ensure_lang_id(german, 1252);
+    version.update().unwrap();
+    resources.close().unwrap();
+
+    let resources = Resources::from_bytes(&resources.to_bytes().unwrap());
+    resources.load().unwrap();
+    let version = resources.get_version_info().unwrap().unwrap();
+    assert_eq!(
+        version.languages(),
+        vec![(LangId(0x0407), 1252), (LangId::EN_US, 1200)]
+    );
+}
+
+#[test]
+fn version_info_language_keys_are_case_insensitive() {
+    let mut data = build_version_info([1, 0, 0, 0], &[("ProductName", "Product")]);
+    // the form emitted by rc.exe
+    let utf16 = |text: &str| -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect()
+    };
+    let (lower, upper) = (utf16("040904b0"), utf16("040904B0"));
+    let offset = data
+        .windows(lower.len())
+        .position(|window| window == lower)
+        .unwrap();
+    data[offset..offset + upper.len()].copy_from_slice(&upper);
+
+    let entries = vec![(resource_type::VERSION, Id::Integer(1), 1033, data)];
+    let resources = Resources::from_bytes(&build_res(&entries));
+    resources.load().unwrap();
+    let mut version = resources.get_version_info().unwrap().unwrap();
+    version.ensure_lang_id(LangId::EN_US, 1200);
+    version.ensure_language("040904b0");
+    assert_eq!(version.languages(), vec![(LangId::EN_US, 1200)]);
+}
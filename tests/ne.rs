@@ -0,0 +1,142 @@
+mod common;
+
+use common::*;
+use winres_edit::icon::IconGroup;
+use winres_edit::ne::{self, NeImage};
+use winres_edit::*;
+
+fn icon_image(len: usize, fill: u8) -> Vec<u8> {
+    let mut image = vec![fill; len];
+    // BITMAPINFOHEADER size
+    image[0..4].copy_from_slice(&40u32.to_le_bytes());
+    image
+}
+
+fn icon_library() -> Vec<u8> {
+    build_ne(
+        &[
+            (resource_type::ICON, Id::Integer(1), icon_image(100, 0x11)),
+            (resource_type::ICON, Id::Integer(2), icon_image(70, 0x22)),
+            (
+                resource_type::GROUP_ICON,
+                Id::Text("MAINICON".into()),
+                build_icon_group(&[(32, 32, 8, 100, 1), (16, 16, 4, 70, 2)]),
+            ),
+            (
+                Id::Text("CUSTOM".into()),
+                Id::Integer(7),
+                b"custom data".to_vec(),
+            ),
+        ],
+        4,
+    )
+}
+
+#[test]
+fn read_ne_resource_table() {
+    let data = icon_library();
+    assert!(ne::is_ne_image(&data));
+    let image = NeImage::parse(&data).unwrap();
+    assert_eq!(image.align_shift, 4);
+    assert_eq!(image.resources.len(), 4);
+
+    let kinds: Vec<(Id, Id)> = image
+        .resources
+        .iter()
+        .map(|r| (r.kind.clone(), r.name.clone()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (resource_type::ICON, Id::Integer(1)),
+            (resource_type::ICON, Id::Integer(2)),
+            (resource_type::GROUP_ICON, Id::Text("MAINICON".into())),
+            (Id::Text("CUSTOM".into()), Id::Integer(7)),
+        ]
+    );
+    for resource in &image.resources {
+        assert_eq!(resource.offset % 16, 0);
+        assert_eq!(resource.length % 16, 0);
+    }
+    assert_eq!(image.resources[0].length, 112);
+}
+
+#[test]
+fn load_resources_from_icon_library() {
+    let path = temp_path("library.icl");
+    std::fs::write(&path, icon_library()).unwrap();
+    assert_eq!(BackendKind::detect(&path).unwrap(), BackendKind::Ne);
+
+    let resources = Resources::new(&path);
+    resources.load().unwrap();
+    let list = resources.list.lock().unwrap();
+    assert_eq!(list.len(), 4);
+    // icons and icon groups are trimmed to their exact size
+    assert_eq!(*list[0].encoded.lock().unwrap(), icon_image(100, 0x11));
+    assert_eq!(*list[1].encoded.lock().unwrap(), icon_image(70, 0x22));
+    assert_eq!(list[2].encoded.lock().unwrap().len(), 6 + 2 * 14);
+    // other resources keep the aligned size
+    assert_eq!(list[3].encoded.lock().unwrap().len(), 16);
+    assert_eq!(&list[3].encoded.lock().unwrap()[..11], b"custom data");
+    assert!(list.iter().all(|r| r.lang == 0));
+    drop(list);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn extract_icon_group() {
+    let resources = Resources::from_bytes(&icon_library());
+    resources.load().unwrap();
+    assert!(resources
+        .get_icon_group(&Id::Text("OTHER".into()))
+        .unwrap()
+        .is_none());
+
+    let ico = resources
+        .get_icon_group(&Id::Text("MAINICON".into()))
+        .unwrap()
+        .unwrap();
+    assert_eq!(&ico[0..6], &[0, 0, 1, 0, 2, 0]);
+    let entry = |index: usize| &ico[6 + index * 16..6 + (index + 1) * 16];
+    assert_eq!(&entry(0)[0..2], &[32, 32]);
+    assert_eq!(&entry(0)[8..12], &100u32.to_le_bytes());
+    assert_eq!(&entry(0)[12..16], &38u32.to_le_bytes());
+    assert_eq!(&entry(1)[0..2], &[16, 16]);
+    assert_eq!(&entry(1)[8..12], &70u32.to_le_bytes());
+    assert_eq!(&entry(1)[12..16], &138u32.to_le_bytes());
+    assert_eq!(&ico[38..138], icon_image(100, 0x11).as_slice());
+    assert_eq!(&ico[138..], icon_image(70, 0x22).as_slice());
+
+    let resource = resources
+        .find(resource_type::GROUP_ICON, Id::Text("MAINICON".into()))
+        .unwrap();
+    let group = IconGroup::parse(&resource.encoded.lock().unwrap()).unwrap();
+    assert_eq!(group.entries.len(), 2);
+    assert_eq!(group.entries[1].bit_count, 4);
+}
+
+#[test]
+fn ne_images_are_read_only() {
+    let mut resources = Resources::from_bytes(&icon_library());
+    resources.load().unwrap();
+    let err = resources.open().err().unwrap();
+    assert!(err.to_string().contains("NE image"));
+}
+
+#[test]
+fn malformed_ne_images_are_rejected() {
+    let data = icon_library();
+    // resource table cut short
+    assert!(NeImage::parse(&data[..0x90]).is_err());
+
+    // invalid alignment shift
+    let mut invalid = data.clone();
+    invalid[0x80] = 16;
+    assert!(NeImage::parse(&invalid).is_err());
+
+    // resource data past the end of the image
+    let truncated = &data[..data.len() - 16];
+    let image = NeImage::parse(truncated).unwrap();
+    assert!(image.resources().is_err());
+}
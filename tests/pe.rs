@@ -0,0 +1,1334 @@
+mod common;
+
+use common::*;
+use winres_edit::pe::{self, PeImage};
+use winres_edit::*;
+
+fn sample_entries() -> Vec<(Id, Id, u16, Vec<u8>)> {
+    vec![
+        (
+            Id::Text("MYTYPE".into()),
+            Id::Text("MYNAME".into()),
+            0,
+            b"custom".to_vec(),
+        ),
+        (resource_type::ICON, Id::Integer(1), 1033, vec![1, 2, 3]),
+        (resource_type::ICON, Id::Integer(2), 1033, vec![4, 5, 6, 7]),
+        (resource_type::VERSION, Id::Integer(1), 1033, vec![0xaa; 10]),
+        (resource_type::VERSION, Id::Integer(1), 1049, vec![0xbb; 12]),
+    ]
+}
+
+fn sample_image(spec: &ImageSpec) -> Vec<u8> {
+    build_image(
+        spec,
+        &[
+            SectionSpec::code(0x300),
+            SectionSpec::resources(sample_entries()),
+        ],
+    )
+}
+
+#[test]
+fn read_pe_resource_directory() {
+    let image = sample_image(&ImageSpec::default());
+    let image = PeImage::parse(&image).unwrap();
+    assert!(image.is_64bit());
+    let entries = image.resources().unwrap();
+    assert_eq!(entries.len(), 5);
+    for (entry, (kind, name, lang, data)) in entries.iter().zip(sample_entries()) {
+        assert_eq!(entry.kind, kind);
+        assert_eq!(entry.name, name);
+        assert_eq!(entry.lang, lang);
+        assert_eq!(entry.data, data);
+    }
+}
+
+#[test]
+fn load_resources_from_pe32_file() {
+    let spec = ImageSpec {
+        machine: IMAGE_FILE_MACHINE_I386,
+        pe32_plus: false,
+        ..Default::default()
+    };
+    let path = temp_path("load-pe32.exe");
+    std::fs::write(&path, sample_image(&spec)).unwrap();
+
+    let resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.load().unwrap();
+    let icon = resources.find(resource_type::ICON, Id::Integer(2)).unwrap();
+    assert_eq!(*icon.encoded.lock().unwrap(), vec![4, 5, 6, 7]);
+    let custom = resources
+        .find(Id::Text("MYTYPE".into()), Id::Text("MYNAME".into()))
+        .unwrap();
+    assert_eq!(*custom.encoded.lock().unwrap(), b"custom".to_vec());
+    assert_eq!(resources.list.lock().unwrap().len(), 5);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn image_without_resources() {
+    let image = build_image(&ImageSpec::default(), &[SectionSpec::code(0x100)]);
+    let image = PeImage::parse(&image).unwrap();
+    assert!(image.resources().unwrap().is_empty());
+}
+
+#[test]
+fn malformed_images_are_rejected() {
+    let image = sample_image(&ImageSpec::default());
+    assert!(PeImage::parse(&image[..0x90]).is_err());
+    assert!(PeImage::parse(b"not an executable").is_err());
+
+    // truncate the resource section data
+    let truncated = &image[..image.len() - 0x180];
+    let parsed = PeImage::parse(truncated);
+    assert!(parsed
+        .map(|image| image.resources().is_err())
+        .unwrap_or(true));
+}
+
+fn write_temp(name: &str, image: &[u8]) -> std::path::PathBuf {
+    let path = temp_path(name);
+    std::fs::write(&path, image).unwrap();
+    path
+}
+
+fn load(path: &std::path::Path) -> Vec<ResourceEntry> {
+    PeImage::parse(&std::fs::read(path).unwrap())
+        .unwrap()
+        .resources()
+        .unwrap()
+}
+
+#[test]
+fn update_and_insert_resources() {
+    let original = sample_image(&ImageSpec::default());
+    let path = write_temp("update.exe", &original);
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .find(resource_type::ICON, Id::Integer(1))
+        .unwrap()
+        .replace(&[9; 300])
+        .unwrap()
+        .update()
+        .unwrap();
+    Resource::new(
+        &resources,
+        resource_type::MANIFEST,
+        Id::Integer(1),
+        0,
+        b"<assembly/>",
+    )
+    .update()
+    .unwrap();
+    Resource::new(
+        &resources,
+        Id::Text("ABC".into()),
+        Id::Text("Name".into()),
+        0,
+        b"abc",
+    )
+    .update()
+    .unwrap();
+    resources.close().unwrap();
+
+    let entries = load(&path);
+    assert_eq!(entries.len(), 7);
+    // named types are sorted ahead of integer types
+    assert_eq!(entries[0].kind, Id::Text("ABC".into()));
+    assert_eq!(entries[1].kind, Id::Text("MYTYPE".into()));
+    let kinds: Vec<_> = entries[2..]
+        .iter()
+        .map(|entry| entry.kind.clone())
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            resource_type::ICON,
+            resource_type::ICON,
+            resource_type::VERSION,
+            resource_type::VERSION,
+            resource_type::MANIFEST,
+        ]
+    );
+    assert_eq!(entries[2].data, vec![9; 300]);
+    assert_eq!(entries[6].data, b"<assembly/>".to_vec());
+
+    // code section must be untouched
+    let updated = std::fs::read(&path).unwrap();
+    assert_eq!(&updated[0x200..0x400], &original[0x200..0x400]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn names_are_stored_uppercased() {
+    let entries = vec![
+        (Id::Text("mytype".into()), Id::Text("a_".into()), 0, vec![1]),
+        (Id::Text("mytype".into()), Id::Text("Ab".into()), 0, vec![2]),
+        (
+            Id::Text("mytype".into()),
+            Id::Text("иконка".into()),
+            0,
+            vec![3],
+        ),
+    ];
+    let image = build_image(
+        &ImageSpec::default(),
+        &[SectionSpec::code(0x100), SectionSpec::resources(vec![])],
+    );
+    let mut resources = Resources::from_bytes(&image);
+    resources.open().unwrap();
+    for (kind, name, lang, data) in &entries {
+        resources
+            .replace_with_args(kind, name, *lang, data)
+            .unwrap();
+    }
+    resources.close().unwrap();
+
+    // the directory holds uppercased names in binary order, as FindResource expects
+    let stored = PeImage::parse(&resources.to_bytes().unwrap())
+        .unwrap()
+        .resources()
+        .unwrap();
+    let names: Vec<(String, String)> = stored
+        .iter()
+        .map(|entry| (entry.kind.to_string(), entry.name.to_string()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("MYTYPE".to_string(), "AB".to_string()),
+            ("MYTYPE".to_string(), "A_".to_string()),
+            ("MYTYPE".to_string(), "ИКОНКА".to_string()),
+        ]
+    );
+    assert_eq!(stored[1].data, vec![1]);
+}
+
+#[test]
+fn remove_and_discard_resources() {
+    let original = sample_image(&ImageSpec::default());
+    let path = write_temp("remove.exe", &original);
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::VERSION, &Id::Integer(1), 1049)
+        .unwrap();
+    resources.discard();
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::VERSION, &Id::Integer(1), 1049)
+        .unwrap();
+    resources
+        .find(resource_type::ICON, Id::Integer(2))
+        .unwrap()
+        .remove()
+        .unwrap();
+    resources.close().unwrap();
+
+    let entries = load(&path);
+    assert_eq!(entries.len(), 3);
+    assert!(!entries.iter().any(|entry| entry.lang == 1049));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn delete_existing_resources() {
+    let path = write_temp("delete-existing.exe", &sample_image(&ImageSpec::default()));
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open_delete_existing_resources().unwrap();
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Integer(7), 1033, &[1, 2])
+        .unwrap();
+
+    // the removal of the existing resources precedes the staged changes
+    let pending = resources.pending_changes();
+    let actions: Vec<ChangeAction> = pending.iter().map(|change| change.action).collect();
+    assert_eq!(actions[..5], [ChangeAction::Remove; 5]);
+    assert_eq!(actions[5..], [ChangeAction::Insert]);
+    assert_eq!(pending[0].old_size, Some(6));
+    assert_eq!(resources.dry_run().unwrap().len(), 6);
+    resources.close().unwrap();
+
+    let entries = load(&path);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].kind, Id::Integer(10));
+    assert_eq!(entries[0].data, vec![1, 2]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn add_resource_section_to_image() {
+    let original = build_image(&ImageSpec::default(), &[SectionSpec::code(0x100)]);
+    let path = write_temp("add-section.exe", &original);
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&resource_type::ICON, &Id::Integer(1), 1033, &[7; 64])
+        .unwrap();
+    resources.close().unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    let image = PeImage::parse(&data).unwrap();
+    assert_eq!(image.sections.len(), 2);
+    assert_eq!(image.sections[1].name(), ".rsrc");
+    assert_eq!(image.size_of_image, 0x3000);
+    let entries = image.resources().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].data, vec![7; 64]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Image layouts of the x86, x64 and ARM64 builds of the same product
+fn image_matrix() -> Vec<(&'static str, ImageSpec)> {
+    vec![
+        (
+            "x86",
+            ImageSpec {
+                machine: IMAGE_FILE_MACHINE_I386,
+                pe32_plus: false,
+                ..Default::default()
+            },
+        ),
+        ("x64", ImageSpec::default()),
+        (
+            "arm64",
+            ImageSpec {
+                machine: IMAGE_FILE_MACHINE_ARM64,
+                file_alignment: 0x1000,
+                section_alignment: 0x10000,
+                ..Default::default()
+            },
+        ),
+        (
+            "x86-low-alignment",
+            ImageSpec {
+                machine: IMAGE_FILE_MACHINE_I386,
+                pe32_plus: false,
+                file_alignment: 0x200,
+                section_alignment: 0x200,
+                ..Default::default()
+            },
+        ),
+        (
+            "arm64-short-directory",
+            ImageSpec {
+                machine: IMAGE_FILE_MACHINE_ARM64,
+                number_of_rva_and_sizes: 3,
+                ..Default::default()
+            },
+        ),
+    ]
+}
+
+fn apply_edits(path: &std::path::Path) {
+    let mut resources = Resources::with_backend(path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&resource_type::VERSION, &Id::Integer(1), 1033, &[0xcc; 700])
+        .unwrap();
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Text("DATA".into()), 0, b"payload")
+        .unwrap();
+    resources
+        .remove_with_args(&resource_type::ICON, &Id::Integer(2), 1033)
+        .unwrap();
+    resources.close().unwrap();
+}
+
+fn check_layout(spec: &ImageSpec, data: &[u8]) {
+    let image = PeImage::parse(data).unwrap();
+    assert_eq!(image.machine, spec.machine);
+    assert_eq!(image.is_64bit(), spec.pe32_plus);
+    assert_eq!(image.size_of_image % spec.section_alignment, 0);
+    for section in &image.sections {
+        assert_eq!(section.virtual_address % spec.section_alignment, 0);
+        assert_eq!(section.pointer_to_raw_data % spec.file_alignment, 0);
+        assert_eq!(section.size_of_raw_data % spec.file_alignment, 0);
+        if spec.section_alignment < 0x1000 {
+            assert_eq!(section.pointer_to_raw_data, section.virtual_address);
+        }
+    }
+    let last = image.sections.last().unwrap();
+    assert_eq!(
+        data.len(),
+        (last.pointer_to_raw_data + last.size_of_raw_data) as usize
+    );
+}
+
+#[test]
+fn identical_edits_across_image_kinds() {
+    let mut results = Vec::new();
+    for (name, spec) in image_matrix() {
+        let original = sample_image(&spec);
+        let path = write_temp(&format!("matrix-{name}.exe"), &original);
+        apply_edits(&path);
+
+        let data = std::fs::read(&path).unwrap();
+        check_layout(&spec, &data);
+        // code section must be untouched
+        let code = &PeImage::parse(&original).unwrap().sections[0];
+        let range = code.pointer_to_raw_data as usize..(code.pointer_to_raw_data + 0x300) as usize;
+        assert_eq!(&data[range.clone()], &original[range], "{name}");
+
+        let entries = load(&path);
+        assert_eq!(entries.len(), 5, "{name}");
+        results.push(entries);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    for entries in &results[1..] {
+        for (entry, expected) in entries.iter().zip(results[0].iter()) {
+            assert_eq!(entry.kind, expected.kind);
+            assert_eq!(entry.name, expected.name);
+            assert_eq!(entry.lang, expected.lang);
+            assert_eq!(entry.data, expected.data);
+        }
+    }
+    let version = results[0]
+        .iter()
+        .find(|entry| entry.kind == resource_type::VERSION && entry.lang == 1033)
+        .unwrap();
+    assert_eq!(version.data, vec![0xcc; 700]);
+}
+
+#[test]
+fn extend_data_directory_table() {
+    for (name, spec) in image_matrix() {
+        let spec = ImageSpec {
+            number_of_rva_and_sizes: 2,
+            ..spec
+        };
+        let original = build_image(&spec, &[SectionSpec::code(0x300)]);
+        assert!(PeImage::parse(&original).unwrap().data_directories.len() == 2);
+        let path = write_temp(&format!("directories-{name}.exe"), &original);
+        apply_edits(&path);
+
+        let data = std::fs::read(&path).unwrap();
+        check_layout(&spec, &data);
+        let image = PeImage::parse(&data).unwrap();
+        assert_eq!(image.data_directories.len(), 3, "{name}");
+        assert_eq!(image.sections.len(), 2, "{name}");
+        assert_eq!(image.sections[0].name(), ".text");
+        let entries = image.resources().unwrap();
+        assert_eq!(entries.len(), 2, "{name}");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn data_past_section_table_is_not_overwritten() {
+    let spec = ImageSpec {
+        number_of_rva_and_sizes: 2,
+        ..Default::default()
+    };
+    let mut original = build_image(&spec, &[SectionSpec::code(0x300)]);
+    // bound import descriptors stored after the section table
+    let file_header = u32::from_le_bytes(original[0x3c..0x40].try_into().unwrap()) as usize + 4;
+    let optional_header_size = u16::from_le_bytes(
+        original[file_header + 16..file_header + 18]
+            .try_into()
+            .unwrap(),
+    );
+    let table_end = file_header + 20 + optional_header_size as usize + 40;
+    original[table_end..table_end + 8].copy_from_slice(b"BOUNDIMP");
+    let path = write_temp("bound-imports.exe", &original);
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&resource_type::ICON, &Id::Integer(1), 1033, &[7; 64])
+        .unwrap();
+    let err = resources.close().err().unwrap();
+    assert!(err.to_string().contains("data directory table"));
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn signed_image(spec: &ImageSpec) -> Vec<u8> {
+    let mut image = sample_image(spec);
+    append_certificate_table(&mut image, &[0x5a; 37]);
+    image
+}
+
+fn certificate(data: &[u8]) -> Option<Vec<u8>> {
+    let image = PeImage::parse(data).unwrap();
+    image.certificate_table().map(|table| {
+        let start = table.virtual_address as usize;
+        assert_eq!(start % 8, 0);
+        data[start..start + table.size as usize].to_vec()
+    })
+}
+
+#[test]
+fn signed_images_are_refused() {
+    let original = signed_image(&ImageSpec::default());
+    let path = write_temp("signed-refuse.exe", &original);
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    assert!(resources.is_signed().unwrap());
+    assert_eq!(resources.signature_policy(), SignaturePolicy::Refuse);
+    assert!(resources.open().is_err());
+    assert!(!resources.is_open());
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+
+    let path_unsigned = write_temp("unsigned.exe", &sample_image(&ImageSpec::default()));
+    assert!(!Resources::new(&path_unsigned).is_signed().unwrap());
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&path_unsigned).unwrap();
+}
+
+#[test]
+fn preserve_certificate_table() {
+    for (name, spec) in image_matrix() {
+        let original = signed_image(&spec);
+        let path = write_temp(&format!("signed-preserve-{name}.exe"), &original);
+
+        let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+        resources.set_signature_policy(SignaturePolicy::Preserve);
+        resources.open().unwrap();
+        resources
+            .replace_with_args(&resource_type::VERSION, &Id::Integer(1), 1033, &[1; 0x4000])
+            .unwrap();
+        resources.close().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        assert!(data.len() > original.len());
+        assert_eq!(certificate(&data), certificate(&original), "{name}");
+        assert_eq!(load(&path).len(), 5);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn strip_certificate_table() {
+    let original = signed_image(&ImageSpec::default());
+    let unsigned = sample_image(&ImageSpec::default());
+    let image = PeImage::parse(&original).unwrap();
+    assert!(image.is_signed());
+    let stripped = image.without_certificate_table().unwrap();
+    assert_eq!(stripped, unsigned);
+
+    let path = write_temp("signed-strip.exe", &original);
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.set_signature_policy(SignaturePolicy::Strip);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&resource_type::ICON, &Id::Integer(1), 1033, &[3; 5])
+        .unwrap();
+    resources.close().unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    assert!(!Resources::new(&path).is_signed().unwrap());
+    assert_eq!(certificate(&data), None);
+    check_layout(&ImageSpec::default(), &data);
+    assert_eq!(load(&path)[1].data, vec![3; 5]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Reference checksum summing 32-bit words (as done by `pefile`)
+fn reference_checksum(data: &[u8]) -> u32 {
+    let field = u32::from_le_bytes(data[0x3c..0x40].try_into().unwrap()) as usize + 24 + 64;
+    let mut padded = data.to_vec();
+    padded.resize(data.len().div_ceil(4) * 4, 0);
+    let mut sum = 0u64;
+    for (index, dword) in padded.chunks(4).enumerate() {
+        if index * 4 == field {
+            continue;
+        }
+        sum += u32::from_le_bytes(dword.try_into().unwrap()) as u64;
+        sum = (sum & 0xffff_ffff) + (sum >> 32);
+    }
+    let mut sum = (sum & 0xffff) + (sum >> 16);
+    sum = (sum + (sum >> 16)) & 0xffff;
+    sum as u32 + data.len() as u32
+}
+
+#[test]
+fn compute_image_checksum() {
+    for (name, spec) in image_matrix() {
+        let mut image = sample_image(&spec);
+        let expected = reference_checksum(&image);
+        assert_eq!(PeImage::parse(&image).unwrap().compute_checksum(), expected);
+        assert!(
+            !PeImage::parse(&image).unwrap().is_checksum_valid(),
+            "{name}"
+        );
+        assert_eq!(pe::update_checksum(&mut image).unwrap(), expected);
+        assert!(PeImage::parse(&image).unwrap().is_checksum_valid());
+
+        // odd sized image (trailing data)
+        image.push(0x7f);
+        let expected = reference_checksum(&image);
+        assert_eq!(PeImage::parse(&image).unwrap().compute_checksum(), expected);
+    }
+}
+
+#[test]
+fn checksum_is_updated_on_commit() {
+    let path = write_temp("checksum.exe", &sample_image(&ImageSpec::default()));
+    assert!(!pe::verify_checksum(&path).unwrap());
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&resource_type::ICON, &Id::Integer(1), 1033, &[1; 99])
+        .unwrap();
+    resources.close().unwrap();
+    assert!(pe::verify_checksum(&path).unwrap());
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.set_update_checksum(false);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&resource_type::ICON, &Id::Integer(1), 1033, &[2; 99])
+        .unwrap();
+    resources.close().unwrap();
+    assert!(!pe::verify_checksum(&path).unwrap());
+
+    // resources borrowing from the file are not affected by fixing the checksum
+    let resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.load().unwrap();
+    assert!(pe::fix_checksum(&path).unwrap());
+    assert!(!pe::fix_checksum(&path).unwrap());
+    assert!(pe::verify_checksum(&path).unwrap());
+    let icon = resources.find(resource_type::ICON, Id::Integer(1)).unwrap();
+    assert_eq!(*icon.encoded.lock().unwrap(), vec![2; 99]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn overlay_image(spec: &ImageSpec, overlay: &[u8]) -> Vec<u8> {
+    let mut entries = sample_entries();
+    entries[3].3 = build_version_info([1, 0, 0, 1], &[("ProductName", "Installer")]);
+    let mut image = build_image(
+        spec,
+        &[SectionSpec::code(0x300), SectionSpec::resources(entries)],
+    );
+    image.extend_from_slice(overlay);
+    image
+}
+
+fn payload() -> Vec<u8> {
+    (0..0x2345u32)
+        .map(|value| (value * 7 % 251) as u8)
+        .collect()
+}
+
+fn edit_version_info(path: &std::path::Path) {
+    let mut resources = Resources::with_backend(path, BackendKind::Pe);
+    resources.set_signature_policy(SignaturePolicy::Preserve);
+    resources.open().unwrap();
+    let mut version = resources.get_version_info().unwrap().unwrap();
+    version
+        .set_version(&[2, 3, 4, 5])
+        .insert_string("ProductName", &"Installer ".repeat(50));
+    version.update().unwrap();
+    // grow the resource section past its current raw size
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Integer(1), 0, &[0x11; 0x3000])
+        .unwrap();
+    resources.close().unwrap();
+}
+
+#[test]
+fn overlay_is_preserved() {
+    for (name, spec) in image_matrix() {
+        let original = overlay_image(&spec, &payload());
+        let path = write_temp(&format!("overlay-{name}.exe"), &original);
+        let resources = Resources::with_backend(&path, BackendKind::Pe);
+        assert_eq!(resources.overlay().unwrap(), payload());
+
+        edit_version_info(&path);
+
+        let data = std::fs::read(&path).unwrap();
+        assert!(data.len() > original.len(), "{name}");
+        assert!(data.ends_with(&payload()));
+        assert_eq!(resources.overlay().unwrap(), payload(), "{name}");
+        check_layout(&spec, &data[..data.len() - payload().len()]);
+
+        let resources = Resources::with_backend(&path, BackendKind::Pe);
+        resources.load().unwrap();
+        let version = resources.get_version_info().unwrap().unwrap();
+        assert_eq!(version.info.file_version.to_string(), "2.3.4.5");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn overlay_and_certificate_table_are_preserved() {
+    let mut original = overlay_image(&ImageSpec::default(), &payload());
+    append_certificate_table(&mut original, &[0x33; 64]);
+    let path = write_temp("overlay-signed.exe", &original);
+    let image = PeImage::parse(&original).unwrap();
+    // the overlay is padded to the alignment of the certificate table
+    assert!(image.overlay().unwrap().starts_with(&payload()));
+    let overlay = image.overlay().unwrap().to_vec();
+
+    edit_version_info(&path);
+
+    let data = std::fs::read(&path).unwrap();
+    let image = PeImage::parse(&data).unwrap();
+    assert_eq!(image.overlay().unwrap(), overlay.as_slice());
+    assert_eq!(certificate(&data), certificate(&original));
+    assert!(data.ends_with(&certificate(&original).unwrap()));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replace_overlay() {
+    let original = overlay_image(&ImageSpec::default(), &payload());
+    let image = PeImage::parse(&original).unwrap();
+    assert_eq!(image.overlay_offset(), original.len() - payload().len());
+    let replaced = image.with_overlay(b"new payload").unwrap();
+    assert_eq!(
+        replaced.len(),
+        original.len() - payload().len() + b"new payload".len()
+    );
+    assert_eq!(
+        PeImage::parse(&replaced).unwrap().overlay().unwrap(),
+        b"new payload"
+    );
+
+    let path = write_temp("overlay-replace.exe", &original);
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    assert!(resources.set_overlay(b"x").is_err());
+    resources.open().unwrap();
+    resources.set_overlay(b"replaced payload").unwrap();
+    resources.close().unwrap();
+    assert_eq!(resources.overlay().unwrap(), b"replaced payload");
+    assert_eq!(load(&path).len(), 5);
+
+    // removing the overlay
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources.set_overlay(&[]).unwrap();
+    resources.close().unwrap();
+    assert!(resources.overlay().unwrap().is_empty());
+    check_layout(&ImageSpec::default(), &std::fs::read(&path).unwrap());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn relocations() -> Vec<u8> {
+    (0..0x180u32)
+        .map(|value| (value * 13 % 241) as u8)
+        .collect()
+}
+
+fn image_with_trailing_section(spec: &ImageSpec, section: SectionSpec) -> Vec<u8> {
+    build_image(
+        spec,
+        &[
+            SectionSpec::code(0x300),
+            SectionSpec::resources(sample_entries()),
+            section,
+        ],
+    )
+}
+
+fn relocation_section() -> SectionSpec {
+    let mut section = SectionSpec::data(".reloc", relocations());
+    section.directory = Some(5);
+    section
+}
+
+fn grow_resources(path: &std::path::Path) {
+    let mut resources = Resources::with_backend(path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Integer(1), 0, &payload())
+        .unwrap();
+    resources.close().unwrap();
+}
+
+#[test]
+fn move_sections_following_resource_section() {
+    for (name, spec) in image_matrix() {
+        let original = image_with_trailing_section(&spec, relocation_section());
+        let path = write_temp(&format!("relocate-{name}.exe"), &original);
+        grow_resources(&path);
+
+        let data = std::fs::read(&path).unwrap();
+        check_layout(&spec, &data);
+        let image = PeImage::parse(&data).unwrap();
+        let reloc = image
+            .sections
+            .iter()
+            .find(|section| section.name() == ".reloc")
+            .unwrap();
+        let start = reloc.pointer_to_raw_data as usize;
+        assert_eq!(&data[start..start + relocations().len()], relocations());
+        assert_eq!(
+            image.size_of_image,
+            image
+                .sections
+                .iter()
+                .map(|section| section.virtual_address
+                    + section.virtual_size.div_ceil(spec.section_alignment)
+                        * spec.section_alignment)
+                .max()
+                .unwrap()
+        );
+
+        if spec.number_of_rva_and_sizes > 5 {
+            // .reloc is moved past the grown resource section
+            let names = image.sections.iter().map(|s| s.name()).collect::<Vec<_>>();
+            assert_eq!(names, [".text", ".rsrc", ".reloc"], "{name}");
+            assert_eq!(
+                image.data_directories[5].virtual_address,
+                reloc.virtual_address
+            );
+            assert_eq!(image.data_directories[5].size, relocations().len() as u32);
+            assert!(
+                image.sections[1].virtual_address + image.sections[1].virtual_size
+                    <= reloc.virtual_address
+            );
+        } else {
+            // not referenced by the data directories, .reloc can not be moved
+            let names = image.sections.iter().map(|s| s.name()).collect::<Vec<_>>();
+            assert_eq!(names, [".text", ".rsrc", ".reloc", ".rsrc"], "{name}");
+            let original = PeImage::parse(&original).unwrap();
+            assert_eq!(reloc.virtual_address, original.sections[2].virtual_address);
+        }
+        let entries = load(&path);
+        assert_eq!(entries.len(), 6, "{name}");
+        assert!(entries.iter().any(|entry| entry.data == payload()));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn append_resource_section_past_fixed_sections() {
+    // leave room for an additional section header
+    let spec = ImageSpec {
+        file_alignment: 0x1000,
+        ..Default::default()
+    };
+    let original = image_with_trailing_section(&spec, SectionSpec::data(".data", vec![0x55; 0x80]));
+    let path = write_temp("relocate-data.exe", &original);
+    grow_resources(&path);
+
+    let data = std::fs::read(&path).unwrap();
+    check_layout(&spec, &data);
+    let image = PeImage::parse(&data).unwrap();
+    let original = PeImage::parse(&original).unwrap();
+    assert_eq!(image.sections.len(), 4);
+    // leading sections are left in place
+    for (section, expected) in image.sections.iter().zip(original.sections.iter()) {
+        assert_eq!(section.virtual_address, expected.virtual_address);
+        assert_eq!(section.pointer_to_raw_data, expected.pointer_to_raw_data);
+    }
+    let rsrc = &image.sections[3];
+    assert_eq!(rsrc.name(), ".rsrc");
+    assert_eq!(
+        image.data_directories[2].virtual_address,
+        rsrc.virtual_address
+    );
+    assert_eq!(
+        image.size_of_image,
+        rsrc.virtual_address + rsrc.virtual_size.div_ceil(0x1000) * 0x1000
+    );
+    assert_eq!(load(&path).len(), 6);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn shrink_resource_section_in_place() {
+    let spec = ImageSpec::default();
+    let original = image_with_trailing_section(&spec, relocation_section());
+    let path = write_temp("relocate-shrink.exe", &original);
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::ICON, &Id::Integer(2), 1033)
+        .unwrap();
+    resources.close().unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    assert_eq!(data.len(), original.len());
+    let image = PeImage::parse(&data).unwrap();
+    let original = PeImage::parse(&original).unwrap();
+    for (section, expected) in image.sections.iter().zip(original.sections.iter()) {
+        assert_eq!(section.virtual_address, expected.virtual_address);
+        assert_eq!(section.pointer_to_raw_data, expected.pointer_to_raw_data);
+        assert_eq!(section.size_of_raw_data, expected.size_of_raw_data);
+    }
+    assert!(image.sections[1].virtual_size < original.sections[1].virtual_size);
+    assert_eq!(load(&path).len(), 4);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn update_image_in_memory() {
+    let original = sample_image(&ImageSpec::default());
+    let mut resources = Resources::from_bytes(&original);
+    assert_eq!(resources.backend_kind().unwrap(), BackendKind::Pe);
+    assert!(!resources.is_signed().unwrap());
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Integer(1), 0, &payload())
+        .unwrap();
+    resources.close().unwrap();
+
+    let data = resources.to_bytes().unwrap();
+    assert_ne!(data, original);
+    check_layout(&ImageSpec::default(), &data);
+    let entries = PeImage::parse(&data).unwrap().resources().unwrap();
+    assert_eq!(entries.len(), 6);
+    assert!(entries.iter().any(|entry| entry.data == payload()));
+
+    // resources can be loaded for extraction only
+    let resources = Resources::from_bytes(&data);
+    resources.load().unwrap();
+    assert_eq!(resources.list.lock().unwrap().len(), 6);
+}
+
+#[test]
+fn save_changes_to_separate_file() {
+    let original = sample_image(&ImageSpec::default());
+    let input = write_temp("save-as-input.exe", &original);
+    let output = temp_path("save-as-output.exe");
+
+    let mut resources = Resources::with_backend(&input, BackendKind::Pe);
+    assert!(resources.save_as(&output).is_err());
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::ICON, &Id::Integer(2), 1033)
+        .unwrap();
+    resources.save_as(&output).unwrap();
+    assert!(!resources.is_open());
+    // the list reflects the unchanged resource file
+    assert_eq!(resources.list.lock().unwrap().len(), 5);
+
+    assert_eq!(std::fs::read(&input).unwrap(), original);
+    assert_eq!(load(&input).len(), 5);
+    assert_eq!(load(&output).len(), 4);
+
+    // in-memory resource files can be saved as well
+    let mut resources = Resources::from_bytes(&original);
+    resources.open().unwrap();
+    resources.save_as(&output).unwrap();
+    assert_eq!(resources.to_bytes().unwrap(), original);
+    assert_eq!(load(&output).len(), 5);
+
+    // saving onto the loaded file replaces it
+    let mut resources = Resources::with_backend(&input, BackendKind::Pe);
+    resources.load().unwrap();
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::ICON, &Id::Integer(2), 1033)
+        .unwrap();
+    resources.save_as(&input).unwrap();
+    assert_eq!(resources.list.lock().unwrap().len(), 4);
+    assert_eq!(load(&input).len(), 4);
+
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+}
+
+fn temp_files(path: &std::path::Path) -> Vec<String> {
+    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+    std::fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_str().unwrap().to_string())
+        .filter(|file| file.starts_with(&format!(".{name}.")) && file.ends_with(".tmp"))
+        .collect()
+}
+
+#[test]
+fn failed_commit_is_reported() {
+    // no room for an additional section header past the fixed `.data` section
+    let original = image_with_trailing_section(
+        &ImageSpec::default(),
+        SectionSpec::data(".data", vec![0x55; 0x80]),
+    );
+    let path = write_temp("commit-failure.exe", &original);
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Integer(1), 0, &payload())
+        .unwrap();
+    assert!(resources.close().is_err());
+    assert!(!resources.is_open());
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+    assert!(temp_files(&path).is_empty());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn keep_backup_of_original_file() {
+    let original = sample_image(&ImageSpec::default());
+    let path = write_temp("backup.exe", &original);
+    let backup = std::path::PathBuf::from(format!("{}.bak", path.display()));
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.set_backup(true);
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::ICON, &Id::Integer(2), 1033)
+        .unwrap();
+    resources.close().unwrap();
+
+    assert_eq!(std::fs::read(&backup).unwrap(), original);
+    assert_eq!(load(&path).len(), 4);
+    assert!(temp_files(&path).is_empty());
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&backup).unwrap();
+}
+
+#[test]
+fn failed_backup_keeps_changes_staged() {
+    let original = sample_image(&ImageSpec::default());
+    let path = write_temp("failed-backup.exe", &original);
+    let backup = std::path::PathBuf::from(format!("{}.bak", path.display()));
+    // a directory in place of the backup file makes the copy fail
+    std::fs::create_dir(&backup).unwrap();
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.set_backup(true);
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::ICON, &Id::Integer(2), 1033)
+        .unwrap();
+    assert!(resources.close().is_err());
+    assert!(resources.is_open());
+    assert_eq!(resources.pending_changes().len(), 1);
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+
+    std::fs::remove_dir(&backup).unwrap();
+    resources.close().unwrap();
+    assert_eq!(std::fs::read(&backup).unwrap(), original);
+    assert_eq!(load(&path).len(), 4);
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&backup).unwrap();
+}
+
+#[test]
+fn inspect_pending_changes() {
+    let original = sample_image(&ImageSpec::default());
+    let path = write_temp("pending.exe", &original);
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    assert!(resources.dry_run().is_err());
+    resources.open().unwrap();
+    resources
+        .find(resource_type::ICON, Id::Integer(1))
+        .unwrap()
+        .replace(&[9; 300])
+        .unwrap()
+        .update()
+        .unwrap();
+    resources
+        .remove_with_args(&resource_type::ICON, &Id::Integer(2), 1033)
+        .unwrap();
+    resources
+        .replace_with_args(&resource_type::MANIFEST, &Id::Integer(1), 0, b"<a/>")
+        .unwrap();
+    resources
+        .replace_with_args(&resource_type::MANIFEST, &Id::Integer(1), 0, b"<assembly/>")
+        .unwrap();
+    // removing a pending insert discards it
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Integer(1), 0, b"x")
+        .unwrap();
+    resources
+        .remove_with_args(&Id::Integer(10), &Id::Integer(1), 0)
+        .unwrap();
+
+    let changes = resources.pending_changes();
+    let summary = changes
+        .iter()
+        .map(|change| {
+            (
+                change.action,
+                change.kind.clone(),
+                change.old_size,
+                change.new_size,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (
+                ChangeAction::Replace,
+                resource_type::ICON,
+                Some(3),
+                Some(300)
+            ),
+            (ChangeAction::Remove, resource_type::ICON, Some(4), None),
+            (
+                ChangeAction::Insert,
+                resource_type::MANIFEST,
+                None,
+                Some(11)
+            ),
+        ]
+    );
+    assert_eq!(changes[2].data().unwrap(), b"<assembly/>");
+
+    // dry run reports the changes without modifying the file
+    assert_eq!(resources.dry_run().unwrap().len(), 3);
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+    assert!(resources.is_open());
+
+    let cleared = resources
+        .clear_pending_change(&resource_type::ICON, &Id::Integer(2), 1033)
+        .unwrap();
+    assert_eq!(cleared.action, ChangeAction::Remove);
+    assert_eq!(resources.pending_changes().len(), 2);
+    resources.close().unwrap();
+    assert!(resources.pending_changes().is_empty());
+
+    let entries = load(&path);
+    assert_eq!(entries.len(), 6);
+    assert!(entries.iter().any(|entry| entry.data == vec![4, 5, 6, 7]));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn dry_run_reports_failures() {
+    let original = image_with_trailing_section(
+        &ImageSpec::default(),
+        SectionSpec::data(".data", vec![0x55; 0x80]),
+    );
+    let path = write_temp("dry-run-failure.exe", &original);
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Integer(1), 0, &payload())
+        .unwrap();
+    assert!(resources.dry_run().is_err());
+
+    resources.clear_pending_changes();
+    assert!(resources.dry_run().unwrap().is_empty());
+    resources.close().unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn overlay_and_directory_changes_are_staged() {
+    let original = overlay_image(&ImageSpec::default(), &payload());
+    let path = write_temp("staged-overlay.exe", &original);
+    let directory = DirectoryMetadata {
+        time_date_stamp: 0x1234_5678,
+        ..Default::default()
+    };
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    assert!(resources.pending_overlay().is_none());
+    resources.set_overlay(b"staged payload").unwrap();
+    resources.set_directory(&directory).unwrap();
+    assert_eq!(resources.pending_overlay().unwrap(), b"staged payload");
+    assert_eq!(resources.pending_directory(), Some(directory));
+    assert!(resources.pending_changes().is_empty());
+    assert!(resources.dry_run().unwrap().is_empty());
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+
+    // discarded along with the resource changes
+    resources.clear_pending_changes();
+    assert!(resources.pending_overlay().is_none());
+    assert!(resources.pending_directory().is_none());
+    resources.close().unwrap();
+    assert_eq!(resources.overlay().unwrap(), payload());
+    assert_eq!(
+        resources.directory().unwrap(),
+        Some(DirectoryMetadata::default())
+    );
+
+    resources.open().unwrap();
+    resources.set_overlay(b"staged payload").unwrap();
+    resources.set_directory(&directory).unwrap();
+    resources.close().unwrap();
+    assert_eq!(resources.overlay().unwrap(), b"staged payload");
+    assert_eq!(resources.directory().unwrap(), Some(directory));
+
+    // file types without an overlay fail the dry run
+    let mut resources = Resources::from_bytes(&build_res(&sample_entries()));
+    resources.open().unwrap();
+    resources.set_overlay(b"payload").unwrap();
+    assert!(resources.dry_run().is_err());
+    resources.clear_pending_changes();
+    assert!(resources.dry_run().is_ok());
+    resources.discard();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn preserve_resource_metadata() {
+    let path = write_temp("metadata.exe", &sample_image(&ImageSpec::default()));
+    let directory = DirectoryMetadata {
+        characteristics: 0,
+        time_date_stamp: 0x6400_0000,
+        major_version: 4,
+        minor_version: 2,
+    };
+
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    assert_eq!(
+        resources.directory().unwrap(),
+        Some(DirectoryMetadata::default())
+    );
+    assert!(resources.set_directory(&directory).is_err());
+    resources.open().unwrap();
+    resources.set_directory(&directory).unwrap();
+    let icon = resources.find(resource_type::ICON, Id::Integer(1)).unwrap();
+    icon.set_metadata(ResourceMetadata {
+        code_page: 1252,
+        ..Default::default()
+    })
+    .unwrap();
+    icon.update().unwrap();
+    resources.close().unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    let image = PeImage::parse(&data).unwrap();
+    assert_eq!(image.resource_directory().unwrap(), Some(directory));
+    let entries = image.resources().unwrap();
+    let code_page = |name: u16| {
+        entries
+            .iter()
+            .find(|entry| entry.kind == resource_type::ICON && entry.name == Id::Integer(name))
+            .unwrap()
+            .metadata
+            .code_page
+    };
+    assert_eq!(code_page(1), 1252);
+    assert_eq!(code_page(2), 0);
+
+    // attributes are retained when the resource data is replaced
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(&resource_type::ICON, &Id::Integer(1), 1033, &[9; 64])
+        .unwrap();
+    let icon = resources.find(resource_type::ICON, Id::Integer(1)).unwrap();
+    assert_eq!(icon.metadata().code_page, 1252);
+    resources.close().unwrap();
+
+    assert_eq!(resources.directory().unwrap(), Some(directory));
+    resources.load().unwrap();
+    let icon = resources.find(resource_type::ICON, Id::Integer(1)).unwrap();
+    assert_eq!(*icon.encoded.lock().unwrap(), vec![9; 64]);
+    assert_eq!(icon.metadata().code_page, 1252);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn deterministic_resource_directory() {
+    let path = write_temp("deterministic.exe", &sample_image(&ImageSpec::default()));
+    let directory = DirectoryMetadata {
+        characteristics: 0,
+        time_date_stamp: 0x6400_0000,
+        major_version: 4,
+        minor_version: 0,
+    };
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.open().unwrap();
+    resources.set_directory(&directory).unwrap();
+    resources.close().unwrap();
+    assert_eq!(resources.directory().unwrap(), Some(directory));
+
+    let edit = |path: &std::path::Path| {
+        let mut resources = Resources::with_backend(path, BackendKind::Pe);
+        resources.set_deterministic(true);
+        resources.open().unwrap();
+        resources
+            .replace_with_args(&Id::Integer(10), &Id::Integer(1), 0, b"data")
+            .unwrap();
+        resources.close().unwrap();
+        std::fs::read(path).unwrap()
+    };
+    let first = edit(&path);
+    let image = PeImage::parse(&first).unwrap();
+    assert_eq!(
+        image.resource_directory().unwrap(),
+        Some(DirectoryMetadata {
+            time_date_stamp: 0,
+            ..directory
+        })
+    );
+    assert_eq!(edit(&path), first);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn resource_data_is_borrowed_from_file() {
+    let path = write_temp("borrowed.exe", &sample_image(&ImageSpec::default()));
+    let mut resources = Resources::with_backend(&path, BackendKind::Pe);
+    resources.load().unwrap();
+    assert!(resources
+        .list
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|resource| resource.encoded.lock().unwrap().is_borrowed()));
+
+    // data is copied once it is replaced
+    let icon = resources.find(resource_type::ICON, Id::Integer(1)).unwrap();
+    icon.encoded.lock().unwrap().to_mut().push(8);
+    assert!(!icon.encoded.lock().unwrap().is_borrowed());
+    assert_eq!(*icon.encoded.lock().unwrap(), [1, 2, 3, 8]);
+    let other = resources.find(resource_type::ICON, Id::Integer(2)).unwrap();
+    assert!(other.encoded.lock().unwrap().is_borrowed());
+
+    resources.open().unwrap();
+    let icon = resources.find(resource_type::ICON, Id::Integer(1)).unwrap();
+    icon.replace(&[1, 2, 3, 8]).unwrap().update().unwrap();
+    resources.close().unwrap();
+    resources.load().unwrap();
+    let icon = resources.find(resource_type::ICON, Id::Integer(1)).unwrap();
+    assert_eq!(*icon.encoded.lock().unwrap(), [1, 2, 3, 8]);
+    assert_eq!(load(&path).len(), 5);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn mapping_bounds() {
+    let mapping = Mapping::from_vec((0..16).collect());
+    assert!(!mapping.is_mapped());
+    let mut data = mapping.slice(4, 8).unwrap();
+    assert!(data.is_borrowed());
+    assert_eq!(data, [4, 5, 6, 7, 8, 9, 10, 11]);
+    data.truncate(2);
+    assert_eq!(data, [4, 5]);
+    assert!(mapping.slice(12, 8).is_err());
+
+    // slices outside of the mapping are copied
+    let other = [1u8, 2, 3];
+    assert!(!mapping.data(&other).is_borrowed());
+    assert!(mapping.data(&mapping[2..6]).is_borrowed());
+}
@@ -0,0 +1,387 @@
+mod common;
+
+use common::*;
+use winres_edit::res::{self, ResFile};
+use winres_edit::*;
+
+fn sample_entries() -> Vec<(Id, Id, u16, Vec<u8>)> {
+    vec![
+        (resource_type::VERSION, Id::Integer(1), 1033, vec![0xaa; 10]),
+        (
+            Id::Integer(10),
+            Id::Text("MYDATA".into()),
+            1033,
+            b"hello".to_vec(),
+        ),
+        (
+            Id::Text("CUSTOM".into()),
+            Id::Text("X".into()),
+            0,
+            vec![1, 2, 3],
+        ),
+    ]
+}
+
+#[test]
+fn read_res_records() {
+    let data = build_res(&sample_entries());
+    assert!(res::is_res_file(&data));
+    let file = ResFile::parse(&data).unwrap();
+    assert_eq!(file.records.len(), 3);
+    for (record, (kind, name, lang, data)) in file.records.iter().zip(sample_entries()) {
+        assert_eq!(record.kind, kind);
+        assert_eq!(record.name, name);
+        assert_eq!(record.lang, lang);
+        assert_eq!(record.data, data);
+        assert_eq!(record.memory_flags, 0x1030);
+    }
+}
+
+#[test]
+fn load_resources_from_res_file() {
+    let path = temp_path("load.res");
+    std::fs::write(&path, build_res(&sample_entries())).unwrap();
+
+    let resources = Resources::new(&path);
+    resources.load().unwrap();
+    assert_eq!(resources.list.lock().unwrap().len(), 3);
+    let data = resources
+        .find(Id::Integer(10), Id::Text("MYDATA".into()))
+        .unwrap();
+    assert_eq!(*data.encoded.lock().unwrap(), b"hello".to_vec());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn malformed_res_files_are_rejected() {
+    let data = build_res(&sample_entries());
+    assert!(ResFile::parse(&data[..data.len() - 4]).is_err());
+    assert!(ResFile::parse(&data[..40]).is_err());
+    assert!(ResFile::parse(b"MZ").is_err());
+}
+
+#[test]
+fn res_round_trip_is_byte_identical() {
+    let data = build_res(&sample_entries());
+    assert_eq!(ResFile::parse(&data).unwrap().to_vec(), data);
+}
+
+#[test]
+fn names_are_stored_uppercased() {
+    let mut file = ResFile::parse(&build_res(&[])).unwrap();
+    file.update(
+        &Id::Text("custom".into()),
+        &Id::Text("MyType".into()),
+        0,
+        Some(b"data"),
+    );
+    // as compiled by rc.exe and llvm-rc from `mytype custom {..}`
+    let names: Vec<u8> = "CUSTOM\0MYTYPE\0"
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    let data = file.to_vec();
+    assert_eq!(&data[40..40 + names.len()], &names[..]);
+    let file = ResFile::parse(&data).unwrap();
+    assert_eq!(file.records[0].name.to_string(), "MYTYPE");
+}
+
+#[test]
+fn save_resources_as_res_file() {
+    let source = temp_path("save-source.res");
+    let target = temp_path("save-target.res");
+    std::fs::write(&source, build_res(&sample_entries())).unwrap();
+
+    let resources = Resources::new(&source);
+    resources.load().unwrap();
+    resources.insert(Resource::new(
+        &resources,
+        resource_type::ICON,
+        Id::Integer(1),
+        1033,
+        &[5; 7],
+    ));
+    resources.save_res(&target).unwrap();
+
+    let data = std::fs::read(&target).unwrap();
+    assert_eq!(&data[..32], &build_res(&[])[..]);
+    let file = ResFile::parse(&data).unwrap();
+    assert_eq!(file.records.len(), 4);
+    // memory flags of loaded resources are retained
+    assert_eq!(file.records[0].memory_flags, 0x1030);
+    assert_eq!(file.records[3].kind, resource_type::ICON);
+    assert_eq!(file.records[3].memory_flags, 0x1010);
+    assert_eq!(file.records[3].data, vec![5; 7]);
+    assert_eq!(data.len() % 4, 0);
+
+    std::fs::remove_file(&source).unwrap();
+    std::fs::remove_file(&target).unwrap();
+}
+
+#[test]
+fn save_res_to_the_loaded_file() {
+    let path = temp_path("save-in-place.res");
+    std::fs::write(&path, build_res(&sample_entries())).unwrap();
+
+    let mut resources = Resources::new(&path);
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::VERSION, &Id::Integer(1), 1033)
+        .unwrap();
+    // the saved file is shorter than the file the resource data is borrowed from
+    resources.save_res(&path).unwrap();
+    assert_eq!(
+        ResFile::parse(&std::fs::read(&path).unwrap())
+            .unwrap()
+            .records
+            .len(),
+        2
+    );
+    assert_eq!(list_summary(&resources), sample_entries()[1..].to_vec());
+
+    resources.save_coff(&path, Machine::X64).unwrap();
+    assert_eq!(list_summary(&resources), sample_entries()[1..].to_vec());
+    resources.discard();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn update_res_file() {
+    let path = temp_path("update.res");
+    std::fs::write(&path, build_res(&sample_entries())).unwrap();
+
+    let mut resources = Resources::new(&path);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(
+            &resource_type::VERSION,
+            &Id::Integer(1),
+            1033,
+            &[1, 2, 3, 4, 5],
+        )
+        .unwrap();
+    resources
+        .remove_with_args(&Id::Text("CUSTOM".into()), &Id::Text("X".into()), 0)
+        .unwrap();
+    resources.close().unwrap();
+
+    let file = ResFile::parse(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(file.records.len(), 2);
+    assert_eq!(file.records[0].data, vec![1, 2, 3, 4, 5]);
+    // memory flags of replaced records are retained
+    assert_eq!(file.records[0].memory_flags, 0x1030);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn backend_is_detected_from_file_contents() {
+    let path = temp_path("detect.res");
+    std::fs::write(&path, build_res(&sample_entries())).unwrap();
+    assert_eq!(
+        Resources::new(&path).backend_kind().unwrap(),
+        BackendKind::Res
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn update_res_file_in_memory() {
+    let original = build_res(&sample_entries());
+    let mut resources = Resources::from_bytes(&original);
+    assert_eq!(resources.backend_kind().unwrap(), BackendKind::Res);
+    resources.open().unwrap();
+    assert_eq!(resources.list.lock().unwrap().len(), 3);
+    resources
+        .remove_with_args(&Id::Text("CUSTOM".into()), &Id::Text("X".into()), 0)
+        .unwrap();
+    // changes are applied to the in-memory copy on close
+    assert_eq!(resources.to_bytes().unwrap(), original);
+    resources.close().unwrap();
+
+    let file = ResFile::parse(&resources.to_bytes().unwrap()).unwrap();
+    assert_eq!(file.records.len(), 2);
+    assert_eq!(file.records[1].data, b"hello".to_vec());
+}
+
+fn list_summary(resources: &Resources) -> Vec<(Id, Id, u16, Vec<u8>)> {
+    resources
+        .list
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|resource| {
+            (
+                resource.kind.clone(),
+                resource.name.clone(),
+                resource.lang,
+                resource.encoded.lock().unwrap().to_vec(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn resource_list_reflects_changes() {
+    let path = temp_path("list.res");
+    std::fs::write(&path, build_res(&sample_entries())).unwrap();
+
+    let mut resources = Resources::new(&path);
+    resources.load().unwrap();
+    resources.load().unwrap();
+    assert_eq!(list_summary(&resources), sample_entries());
+
+    resources.open().unwrap();
+    let version = resources
+        .find(resource_type::VERSION, Id::Integer(1))
+        .unwrap();
+    resources
+        .replace_with_args(&resource_type::VERSION, &Id::Integer(1), 1033, &[7; 4])
+        .unwrap();
+    // resources obtained from the list are updated in place
+    assert_eq!(*version.encoded.lock().unwrap(), vec![7; 4]);
+    resources.insert(Resource::new(
+        &resources,
+        resource_type::MANIFEST,
+        Id::Integer(1),
+        0,
+        b"<assembly/>",
+    ));
+    resources
+        .find(Id::Text("CUSTOM".into()), Id::Text("X".into()))
+        .unwrap()
+        .remove()
+        .unwrap();
+    assert!(resources
+        .find(Id::Text("CUSTOM".into()), Id::Text("X".into()))
+        .is_none());
+    assert!(resources
+        .find(resource_type::MANIFEST, Id::Integer(1))
+        .is_some());
+    assert_eq!(resources.pending_changes().len(), 3);
+
+    // clearing a pending change restores the listed resource
+    resources
+        .clear_pending_change(&resource_type::VERSION, &Id::Integer(1), 1033)
+        .unwrap();
+    assert_eq!(
+        *resources
+            .find(resource_type::VERSION, Id::Integer(1))
+            .unwrap()
+            .encoded
+            .lock()
+            .unwrap(),
+        vec![0xaa; 10]
+    );
+
+    let expected = list_summary(&resources);
+    resources.close().unwrap();
+    assert_eq!(list_summary(&resources), expected);
+    resources.load().unwrap();
+    let mut loaded = list_summary(&resources);
+    let mut expected = expected;
+    loaded.sort_by_key(|entry| format!("{entry:?}"));
+    expected.sort_by_key(|entry| format!("{entry:?}"));
+    assert_eq!(loaded, expected);
+
+    // discarding restores the resources contained in the file
+    let before = list_summary(&resources);
+    resources.open().unwrap();
+    resources
+        .remove_with_args(&resource_type::MANIFEST, &Id::Integer(1), 0)
+        .unwrap();
+    assert_eq!(list_summary(&resources).len(), before.len() - 1);
+    resources.discard();
+    assert_eq!(list_summary(&resources), before);
+
+    // resources deleted on close are not listed
+    resources.open_delete_existing_resources().unwrap();
+    assert!(list_summary(&resources).is_empty());
+    resources.discard();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn res_record_metadata_round_trip() {
+    let path = temp_path("metadata.res");
+    std::fs::write(&path, build_res(&sample_entries())).unwrap();
+
+    let mut resources = Resources::new(&path);
+    resources.open().unwrap();
+    let version = resources
+        .find(resource_type::VERSION, Id::Integer(1))
+        .unwrap();
+    assert_eq!(
+        version.metadata(),
+        ResourceMetadata {
+            memory_flags: Some(0x1030),
+            ..Default::default()
+        }
+    );
+    let metadata = ResourceMetadata {
+        code_page: 0,
+        data_version: 2,
+        memory_flags: Some(0x0030),
+        version: 3,
+        characteristics: 4,
+    };
+    version.set_metadata(metadata).unwrap();
+    version.update().unwrap();
+    // attributes are retained when the resource data is replaced
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Text("MYDATA".into()), 1033, b"bye")
+        .unwrap();
+    // new resources use the default attributes of the resource type
+    resources
+        .replace_with_args(&resource_type::ICON, &Id::Integer(1), 1033, &[1; 8])
+        .unwrap();
+    resources.close().unwrap();
+
+    let file = ResFile::parse(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(file.records[0].metadata(), metadata);
+    assert_eq!(file.records[1].memory_flags, 0x1030);
+    assert_eq!(file.records[1].data, b"bye");
+    assert_eq!(file.records[3].kind, resource_type::ICON);
+    assert_eq!(
+        file.records[3].memory_flags,
+        res::MEMORY_FLAG_MOVEABLE | res::MEMORY_FLAG_DISCARDABLE
+    );
+    assert_eq!(resources.directory().unwrap(), None);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn res_records_borrow_mapped_data() {
+    let data = build_res(&sample_entries());
+    let mapping = Mapping::from_vec(data.clone());
+    let file = ResFile::parse_mapping(&mapping).unwrap();
+    assert!(file.records.iter().all(|record| record.data.is_borrowed()));
+    assert_eq!(file.to_vec(), ResFile::parse(&data).unwrap().to_vec());
+    assert_eq!(file.records[1].data, b"hello");
+}
+
+#[test]
+fn resource_names_are_case_insensitive() {
+    let mut resources = Resources::from_bytes(&build_res(&sample_entries()));
+    resources.open().unwrap();
+    let resource = resources
+        .find(Id::Integer(10), Id::Text("mydata".into()))
+        .unwrap();
+    assert_eq!(resource.name, Id::Text("MYDATA".into()));
+    assert!(resources
+        .find(Id::Text("custom".into()), Id::Text("x".into()))
+        .is_some());
+
+    // updating a resource using a differently cased name replaces it
+    resources
+        .replace_with_args(&Id::Integer(10), &Id::Text("MyData".into()), 1033, b"world")
+        .unwrap();
+    resources.close().unwrap();
+    let file = ResFile::parse(&resources.to_bytes().unwrap()).unwrap();
+    assert_eq!(file.records.len(), 3);
+    assert_eq!(file.records[1].data, b"world".to_vec());
+}
@@ -0,0 +1,206 @@
+mod common;
+
+use common::*;
+use winres_edit::coff;
+use winres_edit::pe::PeImage;
+use winres_edit::res::ResFile;
+use winres_edit::*;
+
+const CYRILLIC: &str = "ИКОНКА";
+const EMOJI: &str = "ICON_😀";
+const MIXED: &str = "数据_Ω_\u{1f4a9}";
+
+fn unicode_entries() -> Vec<(Id, Id, u16, Vec<u8>)> {
+    vec![
+        (
+            resource_type::ICON,
+            Id::Text(CYRILLIC.into()),
+            0,
+            vec![1, 2, 3],
+        ),
+        (resource_type::ICON, Id::Text(EMOJI.into()), 0, vec![4, 5]),
+        (
+            Id::Text(MIXED.into()),
+            Id::Text(EMOJI.into()),
+            1033,
+            vec![6],
+        ),
+    ]
+}
+
+fn names(entries: &[ResourceEntry]) -> Vec<(String, String)> {
+    entries
+        .iter()
+        .map(|entry| (entry.kind.to_string(), entry.name.to_string()))
+        .collect()
+}
+
+#[test]
+fn pe_round_trip_of_unicode_names() {
+    let image = build_image(
+        &ImageSpec::default(),
+        &[
+            SectionSpec::code(0x100),
+            SectionSpec::resources(unicode_entries()),
+        ],
+    );
+    let entries = PeImage::parse(&image).unwrap().resources().unwrap();
+    assert_eq!(entries.len(), 3);
+    assert!(names(&entries).contains(&(MIXED.into(), EMOJI.into())));
+
+    let mut resources = Resources::from_bytes(&image);
+    resources.open().unwrap();
+    resources
+        .replace_with_args(
+            &resource_type::ICON,
+            &Id::Text("иконка".into()),
+            0,
+            &[7; 100],
+        )
+        .unwrap();
+    resources
+        .replace_with_args(&Id::Text(EMOJI.into()), &Id::Text(MIXED.into()), 0, b"new")
+        .unwrap();
+    resources.close().unwrap();
+
+    let entries = PeImage::parse(&resources.to_bytes().unwrap())
+        .unwrap()
+        .resources()
+        .unwrap();
+    assert_eq!(entries.len(), 4);
+    let icon = entries
+        .iter()
+        .find(|entry| entry.name == Id::Text(CYRILLIC.into()))
+        .unwrap();
+    // the name of the replaced resource is retained as stored
+    assert_eq!(icon.name.to_string(), CYRILLIC);
+    assert_eq!(icon.data, vec![7; 100]);
+    assert!(names(&entries).contains(&(EMOJI.into(), MIXED.into())));
+}
+
+#[test]
+fn res_round_trip_of_unicode_names() {
+    let data = build_res(&unicode_entries());
+    let file = ResFile::parse(&data).unwrap();
+    assert_eq!(file.records[0].name, Id::Text(CYRILLIC.into()));
+    assert_eq!(file.records[2].kind.to_string(), MIXED);
+    assert_eq!(file.to_vec(), data);
+
+    let path = temp_path("unicode.res");
+    let resources = Resources::from_bytes(&data);
+    resources.load().unwrap();
+    resources.save_res(&path).unwrap();
+    let saved = ResFile::parse(&std::fs::read(&path).unwrap()).unwrap();
+    let saved: Vec<_> = saved
+        .records
+        .iter()
+        .map(|record| (record.kind.to_string(), record.name.to_string()))
+        .collect();
+    assert_eq!(saved, names(&resource_entries(&resources)));
+    assert!(saved.contains(&(MIXED.into(), EMOJI.into())));
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn resource_entries(resources: &Resources) -> Vec<ResourceEntry> {
+    resources
+        .list
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|resource| {
+            ResourceEntry::new(
+                resource.kind.clone(),
+                resource.name.clone(),
+                resource.lang,
+                &resource.encoded.lock().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn coff_round_trip_of_unicode_names() {
+    let entries: Vec<ResourceEntry> = unicode_entries()
+        .into_iter()
+        .map(|(kind, name, lang, data)| ResourceEntry::new(kind, name, lang, &data))
+        .collect();
+    let object = coff::write_object(&entries, Machine::X64).unwrap();
+    let read = coff::read_resources(&object).unwrap();
+    let mut expected = names(&entries);
+    let mut actual = names(&read);
+    expected.sort();
+    actual.sort();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn version_info_unicode_strings() {
+    let mut entries = unicode_entries();
+    entries.push((
+        resource_type::VERSION,
+        Id::Integer(1),
+        1033,
+        build_version_info([1, 0, 0, 0], &[("ProductName", "Программа")]),
+    ));
+    let mut resources = Resources::from_bytes(&build_res(&entries));
+    resources.open().unwrap();
+    let mut version = resources.get_version_info().unwrap().unwrap();
+    version
+        .insert_string("FileDescription", "Иконки 😀")
+        .insert_string("Comments_数据", "\u{1f4a9}");
+    version.update().unwrap();
+    resources.close().unwrap();
+
+    let resources = Resources::from_bytes(&resources.to_bytes().unwrap());
+    resources.load().unwrap();
+    let version = resources.get_version_info().unwrap().unwrap();
+    let strings = version
+        .children
+        .iter()
+        .find_map(|child| match child {
+            VersionInfoChild::StringFileInfo { tables } => tables.get("040904b0").cloned(),
+            _ => None,
+        })
+        .unwrap();
+    let text = |key: &str| match strings.get(key) {
+        Some(Data::Text(text)) => text.clone(),
+        other => panic!("unexpected string {key}: {other:?}"),
+    };
+    assert_eq!(text("ProductName"), "Программа");
+    assert_eq!(text("FileDescription"), "Иконки 😀");
+    assert_eq!(text("Comments_数据"), "\u{1f4a9}");
+}
+
+/// Replace the UTF-16 name `QZ` in the supplied data with `Q` followed by an unpaired surrogate.
+fn unpaired_surrogate(mut data: Vec<u8>) -> Vec<u8> {
+    let name = [b'Q', 0, b'Z', 0];
+    let offset = data
+        .windows(name.len())
+        .position(|window| window == name)
+        .unwrap();
+    data[offset + 2..offset + 4].copy_from_slice(&0xd800u16.to_le_bytes());
+    data
+}
+
+#[test]
+fn unpaired_surrogates_are_replaced() {
+    let entries = vec![
+        (resource_type::ICON, Id::Text("QZ".into()), 0, vec![1]),
+        (resource_type::ICON, Id::Integer(1), 0, vec![2]),
+    ];
+    let expected = Id::Text("Q\u{fffd}".into());
+
+    let res = ResFile::parse(&unpaired_surrogate(build_res(&entries))).unwrap();
+    assert_eq!(res.records.len(), 2);
+    assert_eq!(res.records[0].name, expected);
+
+    let image = build_image(
+        &ImageSpec::default(),
+        &[SectionSpec::code(0x100), SectionSpec::resources(entries)],
+    );
+    let resources = Resources::from_bytes(&unpaired_surrogate(image));
+    resources.load().unwrap();
+    let list = resources.list.lock().unwrap();
+    assert_eq!(list.len(), 2);
+    assert_eq!(list[0].name, expected);
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::*;
+use winres_edit::*;
+
+fn load_version_info(name: &str, data: Vec<u8>) -> VersionInfo {
+    let path = temp_path(name);
+    let entries = [(resource_type::VERSION, Id::Integer(1), 1033, data)];
+    std::fs::write(&path, build_res(&entries)).unwrap();
+    let resources = Resources::new(&path);
+    resources.load().unwrap();
+    let version = resources.get_version_info().unwrap().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    version
+}
+
+#[test]
+fn version_info_with_trailing_alignment_padding() {
+    // an odd-length last string value leaves VS_VERSIONINFO 2 bytes short of
+    // a DWORD boundary; resource compilers pad the resource data to it
+    let mut data = build_version_info([1, 2, 3, 4], &[("ProductName", "Product1")]);
+    assert_eq!(data.len() % 4, 2);
+    data.extend_from_slice(&[0, 0]);
+
+    let version = load_version_info("padded-version.res", data);
+    assert_eq!(version.info.file_version.to_string(), "1.2.3.4");
+    assert_eq!(version.children.len(), 1);
+}
+
+#[test]
+fn version_info_without_padding() {
+    let data = build_version_info([1, 2, 3, 4], &[("ProductName", "Product")]);
+    let version = load_version_info("unpadded-version.res", data);
+    assert_eq!(version.children.len(), 1);
+}